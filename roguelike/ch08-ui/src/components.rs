@@ -1,5 +1,7 @@
+use std::collections::VecDeque;
 use bracket_lib::prelude::*;
-use specs::{Component,prelude::*};
+use specs::{Component,BitSet,prelude::*};
+use crate::map::Map;
 
 #[derive(Component)]
 pub struct Position {
@@ -10,8 +12,13 @@ pub struct Position {
 #[derive(Component)]
 pub struct Renderable {
     pub glyph: FontCharType,
+    /// Index into the sprite sheet, used instead of the glyph when tile graphics are on.
+    pub sprite: Option<FontCharType>,
     pub fg: RGB,
     pub bg: RGB,
+    /// Decides what shows when things share a tile; lower is drawn on top.
+    /// The player is 0, monsters 1, items 2, corpses and traps 3.
+    pub render_order: i32,
 }
 
 #[derive(Component, Debug)]
@@ -20,19 +27,149 @@ pub struct Player;
 #[derive(Component)]
 pub struct Viewshed {
     pub visible_tiles : Vec<Point>,
+    /// The same tiles as `visible_tiles`, by map index, for constant-time lookups.
+    pub visible : BitSet,
     pub range : i32,
     pub dirty : bool
 }
 
+impl Viewshed {
+    pub fn new(range: i32) -> Self {
+        Viewshed { visible_tiles: Vec::new(), visible: BitSet::new(), range, dirty: true }
+    }
+
+    pub fn set_visible(&mut self, tiles: Vec<Point>, map: &Map) {
+        self.visible.clear();
+        for tile in tiles.iter() {
+            self.visible.add(map.point2d_to_index(*tile) as u32);
+        }
+        self.visible_tiles = tiles;
+        self.dirty = false;
+    }
+
+    pub fn can_see(&self, idx: usize) -> bool {
+        self.visible.contains(idx as u32)
+    }
+}
+
 
 #[derive(Component, Debug)]
 pub struct Monster {}
 
+#[derive(Component, Debug)]
+pub struct Companion {
+    pub leash: i32
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub enum AiState {
+    Idle,
+    Wander { destination: usize },
+    Chase { target: Entity },
+    Search { destination: usize },
+    Flee { from: Entity },
+}
+
+impl AiState {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AiState::Idle => "idle",
+            AiState::Wander { .. } => "wandering",
+            AiState::Chase { .. } => "chasing",
+            AiState::Search { .. } => "searching",
+            AiState::Flee { .. } => "fleeing",
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Item {}
+
+#[derive(Component, Debug)]
+pub struct Consumable {}
+
+#[derive(Component, Debug)]
+pub struct ProvidesFood {}
+
+/// Reveals the whole level when used.
+#[derive(Component, Debug)]
+pub struct MagicMapper {}
+
+#[derive(Component, Debug)]
+pub struct ProvidesTelepathy {
+    pub turns : i32
+}
+
+/// Lets the player sense monsters they can't see, for a while.
+#[derive(Component, Debug)]
+pub struct Telepathy {
+    pub turns : i32
+}
+
+/// What's left of something that died. The name says whose corpse it is.
+#[derive(Component, Debug)]
+pub struct Corpse {}
+
+#[derive(Component, Debug)]
+pub struct ProvidesHealing {
+    pub heal_amount : i32
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct InBackpack {
+    pub owner : Entity
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToPickupItem {
+    pub collected_by : Entity,
+    pub item : Entity
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToUseItem {
+    pub item : Entity
+}
+
+#[derive(Component, Debug, Default)]
+pub struct TargetMemory {
+    pub last_seen: Option<Point>,
+    pub turns_since_seen: i32,
+}
+
+/// The rest of a monster's path to `destination`, so it isn't searched for again every turn.
+#[derive(Component, Debug, Default)]
+pub struct CachedPath {
+    pub destination: usize,
+    pub steps: VecDeque<usize>,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Faction {
+    pub name: String
+}
+
+#[derive(Component, Debug)]
+pub struct Hearing {
+    pub range: i32
+}
+
+#[derive(Component, Debug)]
+pub struct HeardNoise {
+    pub origin: Point
+}
+
 #[derive(Component, Debug)]
 pub struct Name {
     pub name : String
 }
 
+/// Flavour text shown when the player looks at something.
+#[derive(Component, Debug)]
+pub struct Description {
+    pub text : String
+}
+
 #[derive(Component, Debug)]
 pub struct BlocksTile {}
 
@@ -44,11 +181,34 @@ pub struct CombatStats {
     pub power : i32
 }
 
+impl CombatStats {
+    /// How hurt something looks, without giving away exact numbers.
+    pub fn describe_health(&self) -> &'static str {
+        let health = self.hp as f32 / self.max_hp as f32;
+        if health >= 1.0 { "unhurt" }
+        else if health > 0.75 { "scratched" }
+        else if health > 0.5 { "wounded" }
+        else if health > 0.25 { "badly wounded" }
+        else { "nearly dead" }
+    }
+}
+
 #[derive(Component, Debug, Clone)]
 pub struct WantsToMelee {
     pub target : Entity
 }
 
+#[derive(Component, Debug, Clone)]
+pub struct WantsToShoot {
+    pub target : Entity
+}
+
+#[derive(Component, Debug)]
+pub struct RangedWeapon {
+    pub range : i32,
+    pub damage : i32
+}
+
 #[derive(Component, Debug)]
 pub struct SufferDamage {
     pub amount : Vec<i32>
@@ -63,4 +223,93 @@ impl SufferDamage {
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Component, Debug)]
+pub struct MakesNoise {
+    pub volume : Vec<i32>
+}
+
+impl MakesNoise {
+    pub fn new_noise(store: &mut WriteStorage<MakesNoise>, source: Entity, volume: i32) {
+        if let Some(noise) = store.get_mut(source) {
+            noise.volume.push(volume);
+        } else {
+            let noise = MakesNoise { volume : vec![volume] };
+            store.insert(source, noise).expect("Unable to insert noise");
+        }
+    }
+}
+/// A purely visual entity that disappears once its lifetime runs out.
+#[derive(Component, Debug)]
+pub struct ParticleLifetime {
+    pub lifetime_ms : f32
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum HungerState { WellFed, Normal, Hungry, Starving }
+
+impl HungerState {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            HungerState::WellFed => "Well Fed",
+            HungerState::Normal => "Normal",
+            HungerState::Hungry => "Hungry",
+            HungerState::Starving => "Starving",
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct HungerClock {
+    pub state : HungerState,
+    pub duration : i32
+}
+
+const WELL_FED_TURNS: i32 = 20;
+
+impl HungerClock {
+    pub fn well_fed() -> Self {
+        HungerClock { state: HungerState::WellFed, duration: WELL_FED_TURNS }
+    }
+}
+
+/// Not drawn until the player finds it, by noticing it or by searching.
+#[derive(Component, Debug)]
+pub struct Hidden {}
+
+/// Goes off when something steps onto its tile.
+#[derive(Component, Debug)]
+pub struct EntryTrigger {}
+
+#[derive(Component, Debug)]
+pub struct EntityMoved {}
+
+#[derive(Component, Debug)]
+pub struct InflictsDamage {
+    pub damage : i32
+}
+
+#[derive(Component, Debug)]
+pub struct TeleportsVictim {}
+
+#[derive(Component, Debug)]
+pub struct RaisesAlarm {
+    pub volume : i32
+}
+
+#[derive(Component, Debug)]
+pub struct CausesConfusion {
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Confusion {
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct LightSource {
+    pub color : RGB,
+    pub range : i32
+}
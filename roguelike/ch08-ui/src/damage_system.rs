@@ -5,14 +5,21 @@ use super::prelude::*;
 pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
-    type SystemData = ( WriteStorage<'a, CombatStats>,
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, CombatStats>,
                         WriteStorage<'a, SufferDamage> );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut stats, mut damage) = data;
+        let (mut map, positions, mut stats, mut damage) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
+        for (mut stats, damage, pos) in (&mut stats, &damage, (&positions).maybe()).join() {
+            let amount = damage.amount.iter().sum::<i32>();
+            stats.hp -= amount;
+            if let Some(pos) = pos.filter(|_| amount > 0) {
+                let idx = map.point2d_to_index(Point::new(pos.x, pos.y));
+                map.bloodstains.insert(idx);
+            }
         }
 
         damage.clear();
@@ -21,13 +28,19 @@ impl<'a> System<'a> for DamageSystem {
 
 pub fn delete_the_dead(ecs : &mut World) {
     let mut dead : Vec<Entity> = Vec::new();
+    let mut corpses : Vec<(Position, Renderable, String)> = Vec::new();
+    let mut drops : Vec<(Entity, Position)> = Vec::new();
     // Using a scope to make the borrow checker happy
     {
         let combat_stats = ecs.read_storage::<CombatStats>();
         let players = ecs.read_storage::<Player>();
         let names = ecs.read_storage::<Name>();
+        let positions = ecs.read_storage::<Position>();
+        let renderables = ecs.read_storage::<Renderable>();
+        let backpack = ecs.read_storage::<InBackpack>();
         let entities = ecs.entities();
         let mut log = ecs.write_resource::<GameLog>();
+        let mut particles = ecs.write_resource::<ParticleBuilder>();
 
         for (entity, stats) in (&entities, &combat_stats).join() {
             if stats.hp < 1 {
@@ -36,17 +49,55 @@ pub fn delete_the_dead(ecs : &mut World) {
                     None => {
                         let victim_name = names.get(entity);
                         if let Some(victim_name) = victim_name {
-                            log.entries.push(format!("{} is dead", &victim_name.name));
+                            log.add(LogBuilder::combat().actor(&victim_name.name, false).text("is dead."));
+                        }
+                        if let Some(pos) = positions.get(entity) {
+                            particles.request(pos.x, pos.y, RGB::named(RED), RGB::named(BLACK), to_cp437('☼'), 300.0);
+                        }
+                        if let (Some(pos), Some(render)) = (positions.get(entity), renderables.get(entity)) {
+                            let name = victim_name.map_or("something", |name| name.name.as_str());
+                            corpses.push((
+                                Position { x: pos.x, y: pos.y },
+                                Renderable { glyph: render.glyph, sprite: None, fg: RGB::named(DARK_RED), bg: render.bg, render_order: 3 },
+                                format!("corpse of {}", name),
+                            ));
                         }
                         dead.push(entity)
                     }
-                    Some(_) => log.entries.push("You are dead".to_string())
+                    Some(_) => log.add(LogBuilder::combat().text("You are dead."))
                 }
             }
         }
+
+        // Whatever the dead were carrying falls where they did
+        for (item, carried) in (&entities, &backpack).join() {
+            if !dead.contains(&carried.owner) { continue; }
+            if let Some(pos) = positions.get(carried.owner) {
+                drops.push((item, Position { x: pos.x, y: pos.y }));
+            }
+        }
+    }
+
+    {
+        let mut positions = ecs.write_storage::<Position>();
+        let mut backpack = ecs.write_storage::<InBackpack>();
+        for (item, pos) in drops {
+            backpack.remove(item);
+            positions.insert(item, pos).expect("Unable to drop item");
+        }
     }
 
     for victim in dead {
         ecs.delete_entity(victim).expect("Unable to delete");
     }
-}
\ No newline at end of file
+
+    for (pos, render, name) in corpses {
+        ecs.create_entity()
+            .with(pos)
+            .with(render)
+            .with(Item {})
+            .with(Corpse {})
+            .with(Name { name })
+            .build();
+    }
+}
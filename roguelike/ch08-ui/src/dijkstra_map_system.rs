@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+const MAX_DEPTH: f32 = 200.0;
+const FLEE_FACTOR: f32 = -1.2;
+
+/// Shared Dijkstra maps, rebuilt once per monster turn. Monsters roll downhill on them.
+pub struct AiMaps {
+    pub approach: DijkstraMap,
+    pub flee: DijkstraMap,
+    pub items: DijkstraMap,
+}
+
+impl AiMaps {
+    pub fn new(width: i32, height: i32) -> Self {
+        AiMaps {
+            approach: DijkstraMap::new_empty(width, height, MAX_DEPTH),
+            flee: DijkstraMap::new_empty(width, height, MAX_DEPTH),
+            items: DijkstraMap::new_empty(width, height, MAX_DEPTH),
+        }
+    }
+}
+
+/// A map leading to `target`, for chasing something other than the player.
+pub fn approach_map(map: &Map, target: usize) -> DijkstraMap {
+    let mut dm = DijkstraMap::new_empty(map.width, map.height, MAX_DEPTH);
+    DijkstraMap::build(&mut dm, &[target], map);
+    dm
+}
+
+/// Returns the neighbouring tile that is lower than `idx` on the given map, if there is one.
+pub fn downhill_exit(dm: &DijkstraMap, idx: usize, map: &Map) -> Option<usize> {
+    let exit = DijkstraMap::find_lowest_exit(dm, idx, map)?;
+    if dm.map[exit] < dm.map[idx] { Some(exit) } else { None }
+}
+
+fn build_flee_map(approach: &DijkstraMap, flee: &mut DijkstraMap, map: &Map) {
+    let mut open: VecDeque<usize> = VecDeque::new();
+    for (idx, distance) in approach.map.iter().enumerate() {
+        if *distance < f32::MAX {
+            flee.map[idx] = *distance * FLEE_FACTOR;
+            open.push_back(idx);
+        } else {
+            flee.map[idx] = f32::MAX;
+        }
+    }
+
+    // Relax the inverted map so that fleeing monsters head for open space instead of dead ends
+    while let Some(idx) = open.pop_front() {
+        for (exit, cost) in map.get_available_exits(idx) {
+            let candidate = flee.map[idx] + cost;
+            if candidate < flee.map[exit] {
+                flee.map[exit] = candidate;
+                open.push_back(exit);
+            }
+        }
+    }
+}
+
+pub struct DijkstraMapSystem {}
+
+impl<'a> System<'a> for DijkstraMapSystem {
+    type SystemData = ( ReadExpect<'a, Map>,
+                        ReadExpect<'a, Point>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, AiMaps>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Item>,
+                        ReadStorage<'a, Corpse> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, player_pos, runstate, mut ai_maps, positions, items, corpses) = data;
+
+        if *runstate != RunState::MonsterTurn { return; }
+
+        let player_idx = map.point2d_to_index(*player_pos);
+        DijkstraMap::clear(&mut ai_maps.approach);
+        DijkstraMap::build(&mut ai_maps.approach, &[player_idx], &*map);
+
+        let AiMaps { approach, flee, .. } = &mut *ai_maps;
+        build_flee_map(approach, flee, &map);
+
+        // Monsters aren't interested in corpses
+        let item_starts: Vec<usize> = (&positions, &items, !&corpses).join()
+            .map(|(pos, _item, _)| map.point2d_to_index(Point::new(pos.x, pos.y)))
+            .collect();
+        DijkstraMap::clear(&mut ai_maps.items);
+        DijkstraMap::build(&mut ai_maps.items, &item_starts, &*map);
+    }
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reaction { Attack, Ignore, Flee }
+
+/// How members of one faction treat members of another. Unlisted pairs ignore each other.
+#[derive(Default)]
+pub struct FactionTable {
+    /// Keyed by our faction, then theirs, so lookups can borrow both names.
+    reactions: HashMap<String, HashMap<String, Reaction>>,
+}
+
+impl FactionTable {
+    pub fn set(&mut self, mine: &str, theirs: &str, reaction: Reaction) {
+        self.reactions.entry(mine.to_string()).or_default().insert(theirs.to_string(), reaction);
+    }
+
+    pub fn reaction(&self, mine: &str, theirs: &str) -> Reaction {
+        self.reactions.get(mine).and_then(|responses| responses.get(theirs)).copied().unwrap_or(Reaction::Ignore)
+    }
+}
@@ -0,0 +1,285 @@
+use bracket_lib::prelude::*;
+
+/// How fields of view are worked out, for the player, monsters and lights alike.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Default)]
+pub enum FovAlgorithm {
+    /// Symmetric shadowcasting: if A can see B, B can see A.
+    #[default]
+    SymmetricShadowcasting,
+    /// bracket-lib's own `field_of_view`.
+    Bracket,
+    /// Shadowcasting that shows every tile the light touches at all. Sees more, but isn't symmetric.
+    Permissive,
+}
+
+impl FovAlgorithm {
+    /// The next algorithm in turn, for switching between them in game.
+    pub fn next(self) -> Self {
+        match self {
+            FovAlgorithm::SymmetricShadowcasting => FovAlgorithm::Bracket,
+            FovAlgorithm::Bracket => FovAlgorithm::Permissive,
+            FovAlgorithm::Permissive => FovAlgorithm::SymmetricShadowcasting,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            FovAlgorithm::SymmetricShadowcasting => "symmetric shadowcasting",
+            FovAlgorithm::Bracket => "bracket-lib",
+            FovAlgorithm::Permissive => "permissive shadowcasting",
+        }
+    }
+}
+
+pub fn field_of_view_with(algorithm: FovAlgorithm, origin: Point, range: i32, map: &dyn Algorithm2D) -> Vec<Point> {
+    match algorithm {
+        FovAlgorithm::SymmetricShadowcasting => shadowcast(origin, range, map, true),
+        FovAlgorithm::Bracket => {
+            let mut view = field_of_view(origin, range, map);
+            view.retain(|p| map.in_bounds(*p));
+            view
+        }
+        FovAlgorithm::Permissive => shadowcast(origin, range, map, false),
+    }
+}
+
+/// An exact slope, so that tiles sitting right on the edge of a shadow always land on the same side.
+#[derive(Copy, Clone)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Slope { num, den }
+    }
+
+    /// The slope from the origin to the left edge of `col`, measured on the row's centre line.
+    fn of_tile(depth: i32, col: i32) -> Self {
+        Slope::new(2 * col - 1, 2 * depth)
+    }
+
+    /// depth * slope, rounded with ties going up.
+    fn round_up(&self, depth: i32) -> i32 {
+        (2 * depth * self.num + self.den).div_euclid(2 * self.den)
+    }
+
+    /// depth * slope, rounded with ties going down.
+    fn round_down(&self, depth: i32) -> i32 {
+        -(self.den - 2 * depth * self.num).div_euclid(2 * self.den)
+    }
+
+    fn at_most(&self, depth: i32, col: i32) -> bool {
+        self.num * depth <= col * self.den
+    }
+
+    fn at_least(&self, depth: i32, col: i32) -> bool {
+        self.num * depth >= col * self.den
+    }
+}
+
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+/// Maps a (depth, column) pair in one of the four quadrants back onto the map.
+fn transform(quadrant: usize, origin: Point, depth: i32, col: i32) -> Point {
+    match quadrant {
+        0 => Point::new(origin.x + col, origin.y - depth),
+        1 => Point::new(origin.x + col, origin.y + depth),
+        2 => Point::new(origin.x + depth, origin.y + col),
+        _ => Point::new(origin.x - depth, origin.y + col),
+    }
+}
+
+/// Albert Ford's symmetric shadowcasting, done a row at a time in each quadrant.
+/// With `symmetric` off, floor tiles are shown as soon as any part of them is lit.
+fn shadowcast(origin: Point, range: i32, map: &dyn Algorithm2D, symmetric: bool) -> Vec<Point> {
+    let mut visible = vec![origin];
+    if !map.in_bounds(origin) { return Vec::new(); }
+
+    let is_wall = |point: Point| !map.in_bounds(point) || map.is_opaque(map.point2d_to_index(point));
+    let in_range = |point: Point| DistanceAlg::Pythagoras.distance2d(origin, point) <= range as f32;
+
+    for quadrant in 0..4 {
+        let mut rows = vec![Row { depth: 1, start: Slope::new(-1, 1), end: Slope::new(1, 1) }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > range { continue; }
+
+            let mut prev_wall: Option<bool> = None;
+            for col in row.start.round_up(row.depth)..=row.end.round_down(row.depth) {
+                let point = transform(quadrant, origin, row.depth, col);
+                let wall = is_wall(point);
+                let centre_lit = row.start.at_most(row.depth, col) && row.end.at_least(row.depth, col);
+
+                if map.in_bounds(point) && in_range(point) && (wall || centre_lit || !symmetric) {
+                    visible.push(point);
+                }
+                if prev_wall == Some(true) && !wall {
+                    row.start = Slope::of_tile(row.depth, col);
+                }
+                if prev_wall == Some(false) && wall {
+                    rows.push(Row { depth: row.depth + 1, start: row.start, end: Slope::of_tile(row.depth, col) });
+                }
+                prev_wall = Some(wall);
+            }
+            if prev_wall == Some(false) {
+                rows.push(Row { depth: row.depth + 1, start: row.start, end: row.end });
+            }
+        }
+    }
+
+    visible.sort_by_key(|p| (p.y, p.x));
+    visible.dedup();
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    struct TestMap {
+        width: i32,
+        height: i32,
+        walls: Vec<bool>,
+    }
+
+    impl TestMap {
+        /// One string per row, with '#' for walls and anything else for floor.
+        fn from_rows(rows: &[&str]) -> Self {
+            TestMap {
+                width: rows[0].len() as i32,
+                height: rows.len() as i32,
+                walls: rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect(),
+            }
+        }
+
+        fn floor(&self) -> Vec<Point> {
+            (0..self.walls.len()).filter(|idx| !self.walls[*idx]).map(|idx| self.index_to_point2d(idx)).collect()
+        }
+    }
+
+    impl BaseMap for TestMap {
+        fn is_opaque(&self, idx: usize) -> bool {
+            self.walls[idx]
+        }
+    }
+
+    impl Algorithm2D for TestMap {
+        fn dimensions(&self) -> Point {
+            Point::new(self.width, self.height)
+        }
+    }
+
+    const ALGORITHMS: [FovAlgorithm; 3] = [FovAlgorithm::SymmetricShadowcasting, FovAlgorithm::Bracket, FovAlgorithm::Permissive];
+
+    const OPEN_ROOM: [&str; 9] = [
+        "#########",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#########",
+    ];
+
+    const PILLARS: [&str; 9] = [
+        "###########",
+        "#.........#",
+        "#..#...#..#",
+        "#.........#",
+        "#....#....#",
+        "#.........#",
+        "#..#...#..#",
+        "#.........#",
+        "###########",
+    ];
+
+    const DIVIDED: [&str; 7] = [
+        "###########",
+        "#....#....#",
+        "#....#....#",
+        "#....#....#",
+        "#....#....#",
+        "#....#....#",
+        "###########",
+    ];
+
+    fn view(algorithm: FovAlgorithm, map: &TestMap, origin: Point, range: i32) -> HashSet<Point> {
+        field_of_view_with(algorithm, origin, range, map).into_iter().collect()
+    }
+
+    #[test]
+    fn open_room_is_fully_visible() {
+        let map = TestMap::from_rows(&OPEN_ROOM);
+        for algorithm in ALGORITHMS {
+            let visible = view(algorithm, &map, Point::new(4, 4), 8);
+            for point in map.floor() {
+                assert!(visible.contains(&point), "{:?} should see {:?}", algorithm, point);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_view_respects_range() {
+        let map = TestMap::from_rows(&OPEN_ROOM);
+        let origin = Point::new(4, 4);
+        let visible = view(FovAlgorithm::SymmetricShadowcasting, &map, origin, 2);
+        let expected: HashSet<Point> = map.floor().into_iter()
+            .filter(|p| DistanceAlg::Pythagoras.distance2d(origin, *p) <= 2.0)
+            .collect();
+        assert_eq!(visible, expected);
+    }
+
+    #[test]
+    fn walls_block_sight() {
+        let map = TestMap::from_rows(&DIVIDED);
+        for algorithm in ALGORITHMS {
+            let visible = view(algorithm, &map, Point::new(2, 3), 10);
+            assert!(visible.contains(&Point::new(5, 3)), "{:?} should see the dividing wall", algorithm);
+            assert!(visible.iter().all(|p| p.x <= 5), "{:?} sees through the wall", algorithm);
+        }
+    }
+
+    #[test]
+    fn pillar_casts_a_shadow() {
+        let map = TestMap::from_rows(&PILLARS);
+        let origin = Point::new(5, 2);
+        for algorithm in [FovAlgorithm::SymmetricShadowcasting, FovAlgorithm::Permissive] {
+            let visible = view(algorithm, &map, origin, 10);
+            assert!(visible.contains(&Point::new(5, 4)), "{:?} should see the pillar", algorithm);
+            assert!(!visible.contains(&Point::new(5, 5)), "{:?} sees through the pillar", algorithm);
+            assert!(visible.contains(&Point::new(4, 5)), "{:?} should see past the pillar's side", algorithm);
+        }
+    }
+
+    #[test]
+    fn symmetric_shadowcasting_is_symmetric() {
+        let map = TestMap::from_rows(&PILLARS);
+        let floor = map.floor();
+        let views: Vec<HashSet<Point>> = floor.iter()
+            .map(|origin| view(FovAlgorithm::SymmetricShadowcasting, &map, *origin, 10))
+            .collect();
+        for (a, view_a) in floor.iter().zip(views.iter()) {
+            for (b, view_b) in floor.iter().zip(views.iter()) {
+                assert_eq!(view_a.contains(b), view_b.contains(a), "{:?} and {:?} disagree", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn permissive_sees_at_least_as_much() {
+        let map = TestMap::from_rows(&PILLARS);
+        for origin in map.floor() {
+            let symmetric = view(FovAlgorithm::SymmetricShadowcasting, &map, origin, 10);
+            let permissive = view(FovAlgorithm::Permissive, &map, origin, 10);
+            assert!(symmetric.is_subset(&permissive), "permissive sees less from {:?}", origin);
+        }
+    }
+}
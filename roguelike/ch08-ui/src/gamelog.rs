@@ -1,3 +1,118 @@
+use bracket_lib::prelude::*;
+
+/// Entries older than this are dropped.
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LogCategory { Combat, Loot, System }
+
+impl LogCategory {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LogCategory::Combat => "Combat",
+            LogCategory::Loot => "Loot",
+            LogCategory::System => "System",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LogFragment {
+    pub text: String,
+    pub colour: RGB,
+}
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub turn: i32,
+    pub category: LogCategory,
+    pub fragments: Vec<LogFragment>,
+    /// How many times in a row this message came up.
+    pub count: i32,
+}
+
+/// Builds a log entry out of coloured pieces, e.g.
+/// `LogBuilder::combat().actor("Goblin", false).text("hits").actor("Rust", true).damage(4)`.
+/// Pieces are joined with spaces, except before punctuation.
+pub struct LogBuilder {
+    category: LogCategory,
+    fragments: Vec<LogFragment>,
+}
+
+impl LogBuilder {
+    pub fn new(category: LogCategory) -> Self {
+        LogBuilder { category, fragments: Vec::new() }
+    }
+
+    pub fn combat() -> Self { LogBuilder::new(LogCategory::Combat) }
+    pub fn loot() -> Self { LogBuilder::new(LogCategory::Loot) }
+    pub fn system() -> Self { LogBuilder::new(LogCategory::System) }
+
+    pub fn colour<S: ToString>(mut self, colour: (u8, u8, u8), text: S) -> Self {
+        self.fragments.push(LogFragment { text: text.to_string(), colour: RGB::named(colour) });
+        self
+    }
+
+    pub fn text<S: ToString>(self, text: S) -> Self { self.colour(WHITE, text) }
+
+    /// The player stands out from everyone else.
+    pub fn actor(self, name: &str, is_player: bool) -> Self {
+        self.colour(if is_player { YELLOW } else { RED }, name)
+    }
+
+    pub fn item(self, name: &str) -> Self { self.colour(CYAN, name) }
+
+    pub fn damage(self, amount: i32) -> Self { self.colour(ORANGE, format!("-{}hp", amount)) }
+}
+
 pub struct GameLog {
-    pub entries : Vec<String>
-}
\ No newline at end of file
+    pub entries: Vec<LogEntry>,
+    /// The player's turn, counted from the start of the game.
+    pub turn: i32,
+}
+
+impl GameLog {
+    pub fn new() -> Self {
+        GameLog { entries: Vec::new(), turn: 1 }
+    }
+
+    pub fn next_turn(&mut self) {
+        self.turn += 1;
+    }
+
+    /// Adds a message, or bumps the count of the last one if it said the same thing.
+    pub fn add(&mut self, message: LogBuilder) {
+        if let Some(last) = self.entries.last_mut() {
+            if last.category == message.category && last.fragments == message.fragments {
+                last.count += 1;
+                last.turn = self.turn;
+                return;
+            }
+        }
+
+        self.entries.push(LogEntry { turn: self.turn, category: message.category, fragments: message.fragments, count: 1 });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+}
+
+impl Default for GameLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Prints an entry's coloured pieces from (x, y), with "x3" after it if it repeated.
+pub fn print_log_entry(ctx: &mut BTerm, x: i32, y: i32, entry: &LogEntry) {
+    let mut cursor = x;
+    for (i, fragment) in entry.fragments.iter().enumerate() {
+        if i > 0 && !fragment.text.starts_with(['.', ',', '!', '?']) { cursor += 1; }
+        ctx.print_color(cursor, y, fragment.colour, RGB::named(BLACK), &fragment.text);
+        cursor += fragment.text.chars().count() as i32;
+    }
+    if entry.count > 1 {
+        let repeats = format!(" x{}", entry.count);
+        ctx.print_color(cursor, y, RGB::named(GRAY), RGB::named(BLACK), repeats);
+    }
+}
@@ -2,11 +2,23 @@ use bracket_lib::prelude::*;
 use specs::prelude::*;
 use super::prelude::*;
 
+#[derive(PartialEq, Copy, Clone)]
+pub enum ItemMenuResult { Cancel, NoResponse, Selected }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum TargetingResult { Cancel, NoResponse, Selected }
+
+/// The UI console is sparse, so there's no cell under the map to recolour; draw a see-through one instead.
+fn highlight(ctx: &mut BTerm, x: i32, y: i32, colour: (u8, u8, u8)) {
+    ctx.set(x, y, RGBA::from_f32(0.0, 0.0, 0.0, 0.0), RGB::named(colour).to_rgba(0.6), to_cp437(' '));
+}
+
 pub fn draw_ui(ecs: &World, ctx: &mut BTerm) {
     ctx.draw_box(0, 43, 79, 6, RGB::named(WHITE), RGB::named(BLACK));
 
     let combat_stats = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
     let log = ecs.fetch::<GameLog>();
 
     for (_player, stats) in (&players, &combat_stats).join() {
@@ -15,15 +27,23 @@ pub fn draw_ui(ecs: &World, ctx: &mut BTerm) {
         ctx.draw_bar_horizontal(28, 43, 50, stats.hp, stats.max_hp, RGB::named(RED), RGB::named(BLACK));
     }
 
-    let mut y = 44;
-    for s in log.entries.iter().rev() {
-        if y < 49 { ctx.print(2, y, s); }
-        y += 1;
+    for (_player, clock) in (&players, &hunger_clocks).join() {
+        let colour = match clock.state {
+            HungerState::WellFed => RGB::named(GREEN),
+            HungerState::Normal => RGB::named(WHITE),
+            HungerState::Hungry => RGB::named(ORANGE),
+            HungerState::Starving => RGB::named(RED),
+        };
+        ctx.print_color(2, 43, colour, RGB::named(BLACK), format!(" {} ", clock.state.describe()));
+    }
+
+    for (y, entry) in (44..).zip(log.entries.iter().rev().take(5)) {
+        print_log_entry(ctx, 2, y, entry);
     }
 
     // Draw mouse cursor
     let mouse_pos = ctx.mouse_pos();
-    ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(MAGENTA));
+    highlight(ctx, mouse_pos.0, mouse_pos.1, MAGENTA);
     draw_tooltips(ecs, ctx);
 }
 
@@ -31,16 +51,26 @@ fn draw_tooltips(ecs: &World, ctx: &mut BTerm) {
     let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let ai_states = ecs.read_storage::<AiState>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let entities = ecs.entities();
 
     let mouse_pos = ctx.mouse_pos();
     if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height { return; }
-    let mut tooltip: Vec<String> = Vec::new();
-    for (name, position) in (&names, &positions).join() {
+    let mut under_mouse: Vec<(i32, String)> = Vec::new();
+    for (entity, name, position, render, _) in (&entities, &names, &positions, &renderables, !&hidden).join() {
         let idx = map.point2d_to_index(Point::new(position.x, position.y));
         if position.x == mouse_pos.0 && position.y == mouse_pos.1 && map.visible_tiles[idx] {
-            tooltip.push(name.name.to_string());
+            match ai_states.get(entity) {
+                Some(state) if cfg!(debug_assertions) => under_mouse.push((render.render_order, format!("{} ({})", name.name, state.describe()))),
+                _ => under_mouse.push((render.render_order, name.name.to_string())),
+            }
         }
     }
+    // Top of the pile first, the way the tile is drawn
+    under_mouse.sort_by_key(|(render_order, _)| *render_order);
+    let tooltip: Vec<String> = under_mouse.into_iter().map(|(_, s)| s).collect();
 
     if !tooltip.is_empty() {
         let mut width: i32 = 0;
@@ -77,4 +107,286 @@ fn draw_tooltips(ecs: &World, ctx: &mut BTerm) {
             ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(WHITE), RGB::named(GREY), &"<-".to_string());
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn show_inventory(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let entities = ecs.entities();
+
+    let inventory: Vec<(Entity, &Name)> = (&entities, &backpack, &names).join()
+        .filter(|(_, item, _)| item.owner == *player_entity)
+        .map(|(entity, _, name)| (entity, name))
+        .collect();
+
+    let count = inventory.len() as i32;
+    let top = 25 - (count / 2);
+    ctx.draw_box(15, top - 2, 31, count + 3, RGB::named(WHITE), RGB::named(BLACK));
+    ctx.print_color(18, top - 2, RGB::named(YELLOW), RGB::named(BLACK), "Inventory");
+    ctx.print_color(18, top + count + 1, RGB::named(YELLOW), RGB::named(BLACK), "ESCAPE to cancel");
+
+    for (j, (_, name)) in inventory.iter().enumerate() {
+        let y = top + j as i32;
+        ctx.set(17, y, RGB::named(WHITE), RGB::named(BLACK), to_cp437('('));
+        ctx.set(18, y, RGB::named(YELLOW), RGB::named(BLACK), 97 + j as FontCharType);
+        ctx.set(19, y, RGB::named(WHITE), RGB::named(BLACK), to_cp437(')'));
+        ctx.print(21, y, &name.name);
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = letter_to_option(key);
+            if selection > -1 && selection < count {
+                return (ItemMenuResult::Selected, Some(inventory[selection as usize].0));
+            }
+            (ItemMenuResult::NoResponse, None)
+        }
+    }
+}
+
+pub fn ranged_target(ecs: &World, ctx: &mut BTerm, range: i32) -> (TargetingResult, Option<Point>) {
+    let map = ecs.fetch::<Map>();
+    let player_entity = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Point>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+
+    ctx.print_color(5, 0, RGB::named(YELLOW), RGB::named(BLACK), "Select Target (Enter: nearest, Esc: cancel):");
+
+    // Highlight the tiles we could hit, which doesn't include our own
+    let mut available_cells = Vec::new();
+    if let Some(visible) = viewsheds.get(*player_entity) {
+        for tile in visible.visible_tiles.iter().filter(|tile| **tile != *player_pos) {
+            let distance = DistanceAlg::Pythagoras.distance2d(*player_pos, *tile);
+            if distance <= range as f32 && map.has_line_of_sight(*player_pos, *tile) {
+                highlight(ctx, tile.x, tile.y, BLUE);
+                available_cells.push(*tile);
+            }
+        }
+    } else {
+        return (TargetingResult::Cancel, None);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => return (TargetingResult::Cancel, None),
+        Some(key) if key == VirtualKeyCode::Return || ecs.fetch::<KeyMap>().command(key) == Some(Command::Fire) => {
+            let nearest = available_cells.iter()
+                .filter(|tile| map.tile_content[map.point2d_to_index(**tile)].iter()
+                    .any(|e| combat_stats.get(*e).is_some() && player_reaction_to(ecs, *e) == Reaction::Attack))
+                .min_by(|a, b| {
+                    let da = DistanceAlg::Pythagoras.distance2d(*player_pos, **a);
+                    let db = DistanceAlg::Pythagoras.distance2d(*player_pos, **b);
+                    da.total_cmp(&db)
+                });
+            if let Some(target) = nearest {
+                return (TargetingResult::Selected, Some(*target));
+            }
+        }
+        _ => {}
+    }
+
+    let mouse_pos = ctx.mouse_point();
+    if available_cells.contains(&mouse_pos) {
+        highlight(ctx, mouse_pos.x, mouse_pos.y, CYAN);
+        if ctx.left_click {
+            return (TargetingResult::Selected, Some(mouse_pos));
+        }
+    } else {
+        highlight(ctx, mouse_pos.x, mouse_pos.y, RED);
+        if ctx.left_click {
+            return (TargetingResult::Cancel, None);
+        }
+    }
+
+    (TargetingResult::NoResponse, None)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LogViewResult { Close, Browse { scroll: i32, filter: Option<LogCategory> } }
+
+const LOG_FILTERS: [Option<LogCategory>; 4] = [None, Some(LogCategory::Combat), Some(LogCategory::Loot), Some(LogCategory::System)];
+
+/// The whole message log, newest at the bottom. `scroll` counts lines back from the newest entry.
+pub fn show_log(ecs: &World, ctx: &mut BTerm, scroll: i32, filter: Option<LogCategory>) -> LogViewResult {
+    let log = ecs.fetch::<GameLog>();
+    let entries: Vec<&LogEntry> = log.entries.iter()
+        .filter(|entry| filter.is_none_or(|category| entry.category == category))
+        .collect();
+
+    let rows = 44;
+    let max_scroll = i32::max(0, entries.len() as i32 - rows);
+    let scroll = scroll.clamp(0, max_scroll);
+
+    ctx.draw_box(0, 0, 79, 49, RGB::named(WHITE), RGB::named(BLACK));
+    let shown = filter.map_or("All", |category| category.describe());
+    ctx.print_color(2, 0, RGB::named(YELLOW), RGB::named(BLACK), format!(" Message Log: {} ", shown));
+    ctx.print_color(2, 49, RGB::named(YELLOW), RGB::named(BLACK), " Up/Down/PgUp/PgDn: scroll, Tab: filter, ESCAPE to close ");
+
+    let end = entries.len() as i32 - scroll;
+    let start = i32::max(0, end - rows);
+    for (y, entry) in (2..).zip(entries[start as usize..end as usize].iter()) {
+        ctx.print_color(2, y, RGB::named(GRAY), RGB::named(BLACK), format!("{:>5}", entry.turn));
+        print_log_entry(ctx, 9, y, entry);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => LogViewResult::Close,
+        Some(VirtualKeyCode::Up) => LogViewResult::Browse { scroll: i32::min(max_scroll, scroll + 1), filter },
+        Some(VirtualKeyCode::Down) => LogViewResult::Browse { scroll: i32::max(0, scroll - 1), filter },
+        Some(VirtualKeyCode::PageUp) => LogViewResult::Browse { scroll: i32::min(max_scroll, scroll + rows), filter },
+        Some(VirtualKeyCode::PageDown) => LogViewResult::Browse { scroll: i32::max(0, scroll - rows), filter },
+        Some(VirtualKeyCode::Tab) => {
+            let current = LOG_FILTERS.iter().position(|f| *f == filter).unwrap_or(0);
+            LogViewResult::Browse { scroll: 0, filter: LOG_FILTERS[(current + 1) % LOG_FILTERS.len()] }
+        }
+        _ => LogViewResult::Browse { scroll, filter },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LookResult { Close, Looking { cursor: Point } }
+
+const LOOK_PANEL_WIDTH: i32 = 32;
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() { line.push(' '); }
+        line.push_str(word);
+    }
+    if !line.is_empty() { lines.push(line); }
+    lines
+}
+
+/// Everything the player can tell about a tile, a line at a time.
+fn describe_tile(ecs: &World, map: &Map, point: Point) -> Vec<(RGB, String)> {
+    let idx = map.point2d_to_index(point);
+    if !map.revealed_tiles[idx] {
+        return vec![(RGB::named(GRAY), "Unexplored".to_string())];
+    }
+
+    let mut lines = vec![(RGB::named(WHITE), map.tiles[idx].describe().to_string())];
+    if map.bloodstains.contains(&idx) {
+        lines.push((RGB::named(DARK_RED), "There is blood here.".to_string()));
+    }
+    if !map.visible_tiles[idx] {
+        lines.push((RGB::named(GRAY), "You can't see it from here.".to_string()));
+        return lines;
+    }
+
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let names = ecs.read_storage::<Name>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let descriptions = ecs.read_storage::<Description>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let ai_states = ecs.read_storage::<AiState>();
+    let confusion = ecs.read_storage::<Confusion>();
+    let telepathy = ecs.read_storage::<Telepathy>();
+    let hunger_clocks = ecs.read_storage::<HungerClock>();
+
+    let mut here: Vec<(Entity, &Renderable, &Name)> = (&entities, &positions, &renderables, &names, !&hidden).join()
+        .filter(|(_, pos, _, _, _)| pos.x == point.x && pos.y == point.y)
+        .map(|(entity, _, render, name, _)| (entity, render, name))
+        .collect();
+    here.sort_by_key(|(_, render, _)| render.render_order);
+
+    for (entity, render, name) in here {
+        lines.push((RGB::named(BLACK), String::new()));
+        lines.push((render.fg, name.name.clone()));
+        if let Some(stats) = combat_stats.get(entity) {
+            lines.push((RGB::named(WHITE), format!("Health: {}", stats.describe_health())));
+        }
+
+        let mut status: Vec<String> = Vec::new();
+        // What a monster is thinking is only shown while debugging, as with the tooltips
+        if let Some(state) = ai_states.get(entity).filter(|_| cfg!(debug_assertions)) {
+            status.push(state.describe().to_string());
+        }
+        if confusion.get(entity).is_some() {
+            status.push("confused".to_string());
+        }
+        if telepathy.get(entity).is_some() {
+            status.push("telepathic".to_string());
+        }
+        if let Some(clock) = hunger_clocks.get(entity) {
+            if clock.state != HungerState::Normal {
+                status.push(clock.state.describe().to_lowercase());
+            }
+        }
+        if !status.is_empty() {
+            lines.push((RGB::named(WHITE), format!("Status: {}", status.join(", "))));
+        }
+
+        if let Some(description) = descriptions.get(entity) {
+            for line in wrap(&description.text, LOOK_PANEL_WIDTH as usize - 2) {
+                lines.push((RGB::named(GRAY), line));
+            }
+        }
+    }
+    lines
+}
+
+/// Moves a cursor around with the movement keys and describes what is under it in a side panel.
+pub fn look(ecs: &World, ctx: &mut BTerm, cursor: Point) -> LookResult {
+    let map = ecs.fetch::<Map>();
+
+    ctx.print_color(5, 0, RGB::named(YELLOW), RGB::named(BLACK), "Look (movement keys: move, Esc: done):");
+    highlight(ctx, cursor.x, cursor.y, YELLOW);
+
+    // Keep the panel on the far side of the map from the cursor
+    let lines = describe_tile(ecs, &map, cursor);
+    let x = if cursor.x < map.width / 2 { map.width - LOOK_PANEL_WIDTH - 1 } else { 1 };
+    ctx.draw_box(x, 2, LOOK_PANEL_WIDTH, lines.len() as i32 + 1, RGB::named(WHITE), RGB::named(BLACK));
+    for (y, (colour, line)) in (3..).zip(lines.iter()) {
+        ctx.print_color(x + 1, y, *colour, RGB::named(BLACK), line);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::Return) => LookResult::Close,
+        Some(key) => match ecs.fetch::<KeyMap>().command(key).and_then(|command| command.direction()) {
+            Some((dx, dy)) => LookResult::Looking {
+                cursor: Point::new((cursor.x + dx).clamp(0, map.width - 1), (cursor.y + dy).clamp(0, map.height - 1)),
+            },
+            None => LookResult::Looking { cursor },
+        },
+        None => LookResult::Looking { cursor },
+    }
+}
+
+/// Every command and the keys bound to it, straight from the active keymap.
+#[derive(PartialEq, Copy, Clone)]
+pub enum HelpResult { Close, Showing }
+
+pub fn show_help(ecs: &World, ctx: &mut BTerm) -> HelpResult {
+    let keymap = ecs.fetch::<KeyMap>();
+
+    let height = Command::ALL.len() as i32 + 6;
+    ctx.draw_box(10, 2, 59, height, RGB::named(WHITE), RGB::named(BLACK));
+    ctx.print_color(13, 2, RGB::named(YELLOW), RGB::named(BLACK), " Controls ");
+    for (y, command) in (4..).zip(Command::ALL.iter()) {
+        let keys: Vec<String> = keymap.keys_for(*command).into_iter().map(key_name).collect();
+        ctx.print_color(13, y, RGB::named(WHITE), RGB::named(BLACK), command.describe());
+        let bound = if keys.is_empty() { "(unbound)".to_string() } else { keys.join(", ") };
+        ctx.print_color(36, y, RGB::named(CYAN), RGB::named(BLACK), bound);
+    }
+    let y = 5 + Command::ALL.len() as i32;
+    ctx.print_color(13, y, RGB::named(WHITE), RGB::named(BLACK), "Click a tile to travel there");
+    let algorithm = *ecs.fetch::<FovAlgorithm>();
+    ctx.print_color(13, y + 1, RGB::named(WHITE), RGB::named(BLACK), format!("Field of view: {}", algorithm.describe()));
+    ctx.print_color(13, y + 2, RGB::named(GRAY), RGB::named(BLACK), "Keys can be rebound in keymap.json");
+    ctx.print_color(13, 2 + height, RGB::named(YELLOW), RGB::named(BLACK), " ESCAPE to close ");
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => HelpResult::Close,
+        _ => HelpResult::Showing,
+    }
+}
@@ -0,0 +1,53 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+const NORMAL_TURNS: i32 = 200;
+const HUNGRY_TURNS: i32 = 200;
+const STARVATION_DAMAGE: i32 = 1;
+
+/// Ticks the player's hunger clock once per player turn. Starving hurts every turn until they eat.
+pub struct HungerSystem {}
+
+impl<'a> System<'a> for HungerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        WriteStorage<'a, HungerClock>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteExpect<'a, GameLog> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut hunger_clock, player_entity, runstate, mut inflict_damage, mut log) = data;
+
+        if *runstate != RunState::PlayerTurn { return; }
+
+        for (entity, clock) in (&entities, &mut hunger_clock).join() {
+            clock.duration -= 1;
+            if clock.duration > 0 { continue; }
+
+            let message = match clock.state {
+                HungerState::WellFed => {
+                    *clock = HungerClock { state: HungerState::Normal, duration: NORMAL_TURNS };
+                    "You are no longer well fed."
+                }
+                HungerState::Normal => {
+                    *clock = HungerClock { state: HungerState::Hungry, duration: HUNGRY_TURNS };
+                    "You are hungry."
+                }
+                HungerState::Hungry => {
+                    *clock = HungerClock { state: HungerState::Starving, duration: 0 };
+                    "You are starving!"
+                }
+                HungerState::Starving => {
+                    SufferDamage::new_damage(&mut inflict_damage, entity, STARVATION_DAMAGE);
+                    "Your hunger pangs are getting painful!"
+                }
+            };
+            if entity == *player_entity {
+                log.add(LogBuilder::system().colour(ORANGE, message));
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+pub struct ItemCollectionSystem {}
+
+impl<'a> System<'a> for ItemCollectionSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( ReadExpect<'a, Entity>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, WantsToPickupItem>,
+                        WriteStorage<'a, Position>,
+                        ReadStorage<'a, Name>,
+                        WriteStorage<'a, InBackpack> );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut log, mut wants_pickup, mut positions, names, mut backpack) = data;
+
+        for pickup in wants_pickup.join() {
+            positions.remove(pickup.item);
+            backpack.insert(pickup.item, InBackpack{ owner: pickup.collected_by }).expect("Unable to insert backpack entry");
+
+            if pickup.collected_by == *player_entity {
+                if let Some(name) = names.get(pickup.item) {
+                    log.add(LogBuilder::loot().text("You pick up the").item(&name.name).text("."));
+                }
+            }
+        }
+
+        wants_pickup.clear();
+    }
+}
+
+pub struct ItemUseSystem {}
+
+impl<'a> System<'a> for ItemUseSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( ReadExpect<'a, Entity>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, RunState>,
+                        Entities<'a>,
+                        WriteStorage<'a, WantsToUseItem>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, Consumable>,
+                        ReadStorage<'a, ProvidesHealing>,
+                        ReadStorage<'a, ProvidesFood>,
+                        WriteStorage<'a, HungerClock>,
+                        ReadStorage<'a, MagicMapper>,
+                        ReadStorage<'a, ProvidesTelepathy>,
+                        WriteStorage<'a, Telepathy>,
+                        WriteStorage<'a, CombatStats>,
+                        WriteExpect<'a, ParticleBuilder>,
+                        ReadStorage<'a, Position> );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut log, mut runstate, entities, mut wants_use, names, consumables, healing, food, mut hunger_clocks,
+            mappers, telepathy_items, mut telepathy, mut combat_stats, mut particles, positions) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            let item_name = names.get(useitem.item).map_or("item", |name| name.name.as_str());
+
+            if let (Some(healer), Some(stats)) = (healing.get(useitem.item), combat_stats.get_mut(entity)) {
+                stats.hp = i32::min(stats.max_hp, stats.hp + healer.heal_amount);
+                if let Some(pos) = positions.get(entity) {
+                    particles.request(pos.x, pos.y, RGB::named(GREEN), RGB::named(BLACK), to_cp437('♥'), 200.0);
+                }
+                if entity == *player_entity {
+                    log.add(LogBuilder::loot().text("You use the").item(item_name).text(", healing").colour(GREEN, healer.heal_amount).text("hp."));
+                }
+            }
+
+            if let (Some(_food), Some(clock)) = (food.get(useitem.item), hunger_clocks.get_mut(entity)) {
+                *clock = HungerClock::well_fed();
+                if entity == *player_entity {
+                    log.add(LogBuilder::loot().text("You eat the").item(item_name).text("."));
+                }
+            }
+
+            if mappers.get(useitem.item).is_some() && entity == *player_entity {
+                log.add(LogBuilder::loot().text("The map is revealed to you!"));
+                *runstate = RunState::MagicMapReveal { row: 0 };
+            }
+
+            if let Some(provides) = telepathy_items.get(useitem.item) {
+                telepathy.insert(entity, Telepathy { turns: provides.turns }).expect("Unable to insert telepathy");
+                if entity == *player_entity {
+                    log.add(LogBuilder::loot().text("You sense the minds around you."));
+                }
+            }
+
+            if consumables.get(useitem.item).is_some() {
+                entities.delete(useitem.item).expect("Delete failed");
+            }
+        }
+
+        wants_use.clear();
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use bracket_lib::prelude::*;
+use serde::Deserialize;
+
+const DEFAULT_KEYMAP_JSON: &str = include_str!("../keymap.json");
+/// Read at startup, if it's there, to rebind keys without rebuilding the game.
+const KEYMAP_FILE: &str = "keymap.json";
+
+/// Everything the player can do from the map, whichever key it's bound to.
+#[derive(Deserialize, PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    MoveWest, MoveEast, MoveNorth, MoveSouth,
+    MoveNorthEast, MoveNorthWest, MoveSouthEast, MoveSouthWest,
+    Wait, Rest, Search, PickUp, Inventory, Fire, Descend,
+    Look, Explore, MessageLog, ToggleGraphics, CycleFov, Screenshot, Help,
+}
+
+impl Command {
+    /// In the order the help screen lists them.
+    pub const ALL: [Command; 22] = [
+        Command::MoveWest, Command::MoveEast, Command::MoveNorth, Command::MoveSouth,
+        Command::MoveNorthEast, Command::MoveNorthWest, Command::MoveSouthEast, Command::MoveSouthWest,
+        Command::Wait, Command::Rest, Command::Search, Command::PickUp, Command::Inventory, Command::Fire, Command::Descend,
+        Command::Look, Command::Explore, Command::MessageLog, Command::ToggleGraphics, Command::CycleFov, Command::Screenshot, Command::Help,
+    ];
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Command::MoveWest => "Move west",
+            Command::MoveEast => "Move east",
+            Command::MoveNorth => "Move north",
+            Command::MoveSouth => "Move south",
+            Command::MoveNorthEast => "Move north-east",
+            Command::MoveNorthWest => "Move north-west",
+            Command::MoveSouthEast => "Move south-east",
+            Command::MoveSouthWest => "Move south-west",
+            Command::Wait => "Wait a turn",
+            Command::Rest => "Rest until healed",
+            Command::Search => "Search for traps",
+            Command::PickUp => "Pick up",
+            Command::Inventory => "Inventory",
+            Command::Fire => "Fire",
+            Command::Descend => "Go down stairs",
+            Command::Look => "Look around",
+            Command::Explore => "Auto-explore",
+            Command::MessageLog => "Message log",
+            Command::ToggleGraphics => "Toggle tile graphics",
+            Command::CycleFov => "Cycle field of view",
+            Command::Screenshot => "Screenshot",
+            Command::Help => "Help",
+        }
+    }
+
+    /// The step a movement command takes. Also moves the look cursor.
+    pub fn direction(&self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveWest => Some((-1, 0)),
+            Command::MoveEast => Some((1, 0)),
+            Command::MoveNorth => Some((0, -1)),
+            Command::MoveSouth => Some((0, 1)),
+            Command::MoveNorthEast => Some((1, -1)),
+            Command::MoveNorthWest => Some((-1, -1)),
+            Command::MoveSouthEast => Some((1, 1)),
+            Command::MoveSouthWest => Some((-1, 1)),
+            _ => None,
+        }
+    }
+}
+
+/// Keys that can be named in a keymap file, by their `VirtualKeyCode` names.
+const BINDABLE_KEYS: &[VirtualKeyCode] = &[
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E, VirtualKeyCode::F,
+    VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J, VirtualKeyCode::K, VirtualKeyCode::L,
+    VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O, VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R,
+    VirtualKeyCode::S, VirtualKeyCode::T, VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X,
+    VirtualKeyCode::Y, VirtualKeyCode::Z,
+    VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4, VirtualKeyCode::F5, VirtualKeyCode::F6,
+    VirtualKeyCode::F7, VirtualKeyCode::F8, VirtualKeyCode::F9, VirtualKeyCode::F10, VirtualKeyCode::F11, VirtualKeyCode::F12,
+    VirtualKeyCode::Left, VirtualKeyCode::Right, VirtualKeyCode::Up, VirtualKeyCode::Down,
+    VirtualKeyCode::Insert, VirtualKeyCode::Delete, VirtualKeyCode::Home, VirtualKeyCode::End,
+    VirtualKeyCode::PageUp, VirtualKeyCode::PageDown,
+    VirtualKeyCode::Space, VirtualKeyCode::Return, VirtualKeyCode::Back, VirtualKeyCode::Tab,
+    VirtualKeyCode::Numpad0, VirtualKeyCode::Numpad1, VirtualKeyCode::Numpad2, VirtualKeyCode::Numpad3, VirtualKeyCode::Numpad4,
+    VirtualKeyCode::Numpad5, VirtualKeyCode::Numpad6, VirtualKeyCode::Numpad7, VirtualKeyCode::Numpad8, VirtualKeyCode::Numpad9,
+    VirtualKeyCode::Apostrophe, VirtualKeyCode::Backslash, VirtualKeyCode::Comma, VirtualKeyCode::Equals, VirtualKeyCode::Grave,
+    VirtualKeyCode::LBracket, VirtualKeyCode::RBracket, VirtualKeyCode::Minus, VirtualKeyCode::Period, VirtualKeyCode::Semicolon,
+    VirtualKeyCode::Slash, VirtualKeyCode::NumpadAdd, VirtualKeyCode::NumpadSubtract, VirtualKeyCode::NumpadEnter,
+];
+
+pub fn key_name(key: VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|key| key_name(*key).eq_ignore_ascii_case(name))
+}
+
+pub struct KeyMap {
+    bindings: HashMap<VirtualKeyCode, Command>,
+}
+
+impl KeyMap {
+    /// The default bindings, with any rebinding from `keymap.json` applied on top.
+    /// Returns whatever was wrong with that file, so it can be shown to the player.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut keymap = KeyMap { bindings: HashMap::new() };
+        let mut problems = Vec::new();
+        keymap.apply(DEFAULT_KEYMAP_JSON, &mut problems).expect("Unable to parse keymap.json");
+
+        // Not there (or no file system, as in the browser) just means the defaults
+        if let Ok(json) = std::fs::read_to_string(KEYMAP_FILE) {
+            if let Err(e) = keymap.apply(&json, &mut problems) {
+                problems.push(format!("{} ignored: {}", KEYMAP_FILE, e));
+            }
+        }
+        (keymap, problems)
+    }
+
+    /// Each command listed replaces that command's keys. A key bound here is taken from whatever had it before.
+    /// A key listed under two commands stays with the one the help screen lists first.
+    fn apply(&mut self, json: &str, problems: &mut Vec<String>) -> Result<(), serde_json::Error> {
+        let mut config: HashMap<Command, Vec<String>> = serde_json::from_str(json)?;
+        let mut claimed: HashMap<VirtualKeyCode, Command> = HashMap::new();
+        for command in Command::ALL {
+            let Some(keys) = config.remove(&command) else { continue; };
+            self.bindings.retain(|_, bound| *bound != command);
+            for name in keys {
+                let Some(key) = key_from_name(&name) else {
+                    problems.push(format!("{}: unknown key \"{}\" for {}", KEYMAP_FILE, name, command.describe()));
+                    continue;
+                };
+                match claimed.get(&key) {
+                    Some(first) if *first != command => problems.push(format!("{}: \"{}\" is bound to both {} and {}, keeping {}",
+                        KEYMAP_FILE, name, first.describe(), command.describe(), first.describe())),
+                    _ => {
+                        claimed.insert(key, command);
+                        self.bindings.insert(key, command);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn command(&self, key: VirtualKeyCode) -> Option<Command> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// The keys bound to a command, in a stable order for display.
+    pub fn keys_for(&self, command: Command) -> Vec<VirtualKeyCode> {
+        let mut keys: Vec<VirtualKeyCode> = self.bindings.iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort();
+        keys
+    }
+}
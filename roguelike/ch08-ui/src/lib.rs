@@ -2,13 +2,31 @@ mod components;
 mod map;
 mod state;
 mod player;
+mod travel;
+mod fov;
 mod visibility_system;
+mod lighting_system;
 mod monster_ai_system;
+mod dijkstra_map_system;
 mod map_indexing_system;
+mod memory_system;
 mod melee_combat_system;
+mod ranged_combat_system;
 mod damage_system;
+mod inventory_system;
+mod hunger_system;
+mod telepathy_system;
+mod noise_system;
+mod trigger_system;
+mod particle_system;
+mod render;
 mod gui;
+mod keymap;
 mod gamelog;
+mod faction;
+pub mod spawner;
+mod raws;
+mod random_table;
 
 
 pub mod prelude {
@@ -16,11 +34,28 @@ pub mod prelude {
     pub use crate::map::*;
     pub use crate::state::*;
     pub use crate::player::*;
+    pub use crate::travel::*;
+    pub use crate::fov::*;
     pub use crate::visibility_system::*;
+    pub use crate::lighting_system::*;
     pub use crate::monster_ai_system::*;
+    pub use crate::dijkstra_map_system::*;
     pub use crate::map_indexing_system::*;
+    pub use crate::memory_system::*;
     pub use crate::melee_combat_system::*;
+    pub use crate::ranged_combat_system::*;
     pub use crate::damage_system::*;
+    pub use crate::inventory_system::*;
+    pub use crate::hunger_system::*;
+    pub use crate::telepathy_system::*;
+    pub use crate::noise_system::*;
+    pub use crate::trigger_system::*;
+    pub use crate::particle_system::*;
+    pub use crate::render::*;
     pub use crate::gui::*;
+    pub use crate::keymap::*;
     pub use crate::gamelog::*;
+    pub use crate::faction::*;
+    pub use crate::raws::*;
+    pub use crate::random_table::*;
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+/// Adds up every light source's contribution into `Map::light`. Each light only reaches what it can see.
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadExpect<'a, FovAlgorithm>,
+                        WriteExpect<'a, FovCache>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, LightSource> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, algorithm, mut cache, positions, lights) = data;
+
+        for light in map.light.iter_mut() {
+            *light = RGB::from_f32(0.0, 0.0, 0.0);
+        }
+
+        for (pos, source) in (&positions, &lights).join() {
+            let origin = Point::new(pos.x, pos.y);
+            let range = source.range as f32;
+            let lit_tiles = cache.view(origin, source.range, &map, *algorithm).clone();
+            for tile in lit_tiles.iter() {
+                let intensity = (range - DistanceAlg::Pythagoras.distance2d(origin, *tile)) / range;
+                if intensity <= 0.0 { continue; }
+
+                let idx = map.point2d_to_index(*tile);
+                let light = &mut map.light[idx];
+                light.r = f32::min(1.0, light.r + source.color.r * intensity);
+                light.g = f32::min(1.0, light.g + source.color.g * intensity);
+                light.b = f32::min(1.0, light.b + source.color.b * intensity);
+            }
+        }
+    }
+}
@@ -1,13 +1,13 @@
 use bracket_lib::prelude::*;
 use specs::prelude::*;
 use roguelike::prelude::*;
+use roguelike::spawner;
 
 const WIDTH: i32 = 80;
 const HEIGHT: i32 = 50;
 
 fn main() -> BError {
-    let mut ctx = BTermBuilder::simple80x50()
-        //.with_tile_dimensions(16, 16)
+    let mut ctx = terminal(WIDTH, HEIGHT)
         .with_title("Walk monster")
         .build()?;
 
@@ -21,48 +21,76 @@ fn main() -> BError {
     gs.ecs.register::<Player>();
     gs.ecs.register::<Viewshed>();
     gs.ecs.register::<Monster>();
+    gs.ecs.register::<Companion>();
+    gs.ecs.register::<AiState>();
+    gs.ecs.register::<TargetMemory>();
+    gs.ecs.register::<CachedPath>();
     gs.ecs.register::<Name>();
+    gs.ecs.register::<Description>();
+    gs.ecs.register::<Item>();
+    gs.ecs.register::<Consumable>();
+    gs.ecs.register::<Corpse>();
+    gs.ecs.register::<ProvidesHealing>();
+    gs.ecs.register::<ProvidesFood>();
+    gs.ecs.register::<HungerClock>();
+    gs.ecs.register::<InBackpack>();
+    gs.ecs.register::<WantsToPickupItem>();
+    gs.ecs.register::<WantsToUseItem>();
     gs.ecs.register::<BlocksTile>();
     gs.ecs.register::<CombatStats>();
     gs.ecs.register::<WantsToMelee>();
+    gs.ecs.register::<WantsToShoot>();
+    gs.ecs.register::<RangedWeapon>();
     gs.ecs.register::<SufferDamage>();
+    gs.ecs.register::<Hearing>();
+    gs.ecs.register::<Faction>();
+    gs.ecs.register::<HeardNoise>();
+    gs.ecs.register::<MakesNoise>();
+    gs.ecs.register::<ParticleLifetime>();
+    gs.ecs.register::<Hidden>();
+    gs.ecs.register::<EntryTrigger>();
+    gs.ecs.register::<EntityMoved>();
+    gs.ecs.register::<InflictsDamage>();
+    gs.ecs.register::<TeleportsVictim>();
+    gs.ecs.register::<RaisesAlarm>();
+    gs.ecs.register::<CausesConfusion>();
+    gs.ecs.register::<Confusion>();
+    gs.ecs.register::<MagicMapper>();
+    gs.ecs.register::<ProvidesTelepathy>();
+    gs.ecs.register::<Telepathy>();
+    gs.ecs.register::<LightSource>();
 
-    let map = Map::new_map_rooms_and_corridors(WIDTH, HEIGHT-7);
+    let map = Map::new_map_rooms_and_corridors(WIDTH, HEIGHT-7, 1);
     let player_center = map.rooms[0].center();
-    let player_entity = gs.ecs.create_entity()
-        .with(Position { x: player_center.x, y: player_center.y })
-        .with(Renderable { glyph: to_cp437('@'), fg: RGB::named(YELLOW), bg: RGB::named(BLACK) })
-        .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
-        .with(Player {})
-        .with(Name { name: "Rust".to_string() })
-        .with(CombatStats{ max_hp: 30, hp: 30, defense: 2, power: 5 })
-        .build();
-
-    let mut rng = RandomNumberGenerator::new();
-    for (id, room) in map.rooms.iter().skip(1).enumerate() {
-        let roll = rng.roll_dice(1, 2);
-        let (glyph, name) = match roll {
-            1 => (to_cp437('g'), "xG".to_string()),
-            _ => (to_cp437('o'), "xO".to_string())
-        };
-
-        let pos = room.center();
-        gs.ecs.create_entity()
-            .with(Position { x: pos.x, y: pos.y })
-            .with(Renderable { glyph, fg: RGB::named(RED), bg: RGB::named(BLACK) })
-            .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
-            .with(Monster {})
-            .with(Name { name: format!("Room{}_{}", id, &name) })
-            .with(BlocksTile{})
-            .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4 })
-            .build();
-    }
+    let raws = RawMaster::load();
+    gs.ecs.insert(raws.faction_table());
+    gs.ecs.insert(raws);
+    gs.ecs.insert(RandomNumberGenerator::new());
+    gs.ecs.insert(AiMaps::new(map.width, map.height));
+    gs.ecs.insert(FovAlgorithm::default());
+    gs.ecs.insert(FovCache::new());
+    gs.ecs.insert(GraphicsMode::default());
+    gs.ecs.insert(map);
 
+    let player_entity = spawner::player(&mut gs.ecs, player_center.x, player_center.y);
+    spawner::companion(&mut gs.ecs, player_center.x + 1, player_center.y);
+    spawner::populate_rooms(&mut gs.ecs);
 
-    gs.ecs.insert(map);
     gs.ecs.insert(Point::new(player_center.x, player_center.y));
     gs.ecs.insert(player_entity);
     gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(GameLog{ entries : vec!["Welcome to Rusty Roguelike".to_string()] });
+    gs.ecs.insert(ParticleBuilder::new());
+    let (keymap, keymap_problems) = KeyMap::load();
+    let help_keys: Vec<String> = keymap.keys_for(Command::Help).into_iter().map(key_name).collect();
+    let mut log = GameLog::new();
+    log.add(LogBuilder::system().text("Welcome to").colour(CYAN, "Rusty Roguelike").text("."));
+    if !help_keys.is_empty() {
+        log.add(LogBuilder::system().text("Press").colour(YELLOW, help_keys.join(" or ")).text("for help."));
+    }
+    for problem in keymap_problems {
+        log.add(LogBuilder::system().colour(ORANGE, problem));
+    }
+    gs.ecs.insert(keymap);
+    gs.ecs.insert(log);
     main_loop(ctx, gs)
 }
@@ -1,12 +1,39 @@
-use super::prelude::*;
 use bracket_lib::prelude::*;
 use specs::*;
 use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::render::{GraphicsMode, RenderLayer, sprite_colours, submit};
+
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+/// Extra cost of stepping onto a trap, so paths go around one if there's a reasonable way.
+const TRAP_PATH_COST: f32 = 10.0;
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum TileType {
     Wall,
     Floor,
+    DownStairs,
+}
+
+impl TileType {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TileType::Wall => "A wall",
+            TileType::Floor => "Floor",
+            TileType::DownStairs => "Stairs leading down",
+        }
+    }
+}
+
+/// What an entity looked like and where it stood when the player last saw it.
+pub struct RememberedEntity {
+    pub point: Point,
+    pub glyph: FontCharType,
+    pub sprite: Option<FontCharType>,
+    pub fg: RGB,
+    pub layer: RenderLayer,
+    pub render_order: i32,
 }
 
 pub struct Map {
@@ -14,17 +41,31 @@ pub struct Map {
     pub rooms: Vec<Rect>,
     pub width: i32,
     pub height: i32,
+    pub depth: i32,
+    /// Different for every generated map. Tiles don't change once a map is built, so this is all
+    /// cached fields of view need to know whether they are stale.
+    pub revision: u64,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
+    /// Tiles with a trap on them. Monsters know where every trap is and avoid them when pathing,
+    /// but will still cross one rather than be cut off.
+    pub trapped: Vec<bool>,
     pub tile_content: Vec<Vec<Entity>>,
+    pub bloodstains: HashSet<usize>,
+    /// How much light reaches each tile, filled in by `LightingSystem`.
+    pub light: Vec<RGB>,
+    pub remembered: HashMap<Entity, RememberedEntity>,
 }
 
 impl Map {
     fn is_exit_valid(&self, x: i32, y: i32) -> bool {
         if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 { return false; }
-        let idx = self.point2d_to_index(Point::new(x, y));
-        !self.blocked[idx]
+        !self.blocked[self.point2d_to_index(Point::new(x, y))]
+    }
+
+    fn exit_cost(&self, idx: usize, distance: f32) -> f32 {
+        if self.trapped[idx] { distance + TRAP_PATH_COST } else { distance }
     }
 
     fn fill_floor(&mut self, point: Point) {
@@ -64,9 +105,25 @@ impl Map {
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
+        for trapped in self.trapped.iter_mut() {
+            *trapped = false;
+        }
     }
 
-    pub fn new_map_rooms_and_corridors(width: i32, height: i32) -> Self {
+    /// True when nothing opaque lies on the Bresenham line between the two points.
+    pub fn has_line_of_sight(&self, start: Point, end: Point) -> bool {
+        Bresenham::new(start, end)
+            .skip(1)
+            .all(|point| !self.is_opaque(self.point2d_to_index(point)))
+    }
+
+    /// The light reaching a tile, but never darker than `MIN_LIGHT`.
+    pub fn light_at(&self, idx: usize) -> RGB {
+        let light = self.light[idx];
+        RGB::from_f32(f32::max(MIN_LIGHT, light.r), f32::max(MIN_LIGHT, light.g), f32::max(MIN_LIGHT, light.b))
+    }
+
+    pub fn new_map_rooms_and_corridors(width: i32, height: i32, depth: i32) -> Self {
         const MAX_ROOMS: i32 = 30;
         const MIN_SIZE: i32 = 6;
         const MAX_SIZE: i32 = 10;
@@ -77,10 +134,16 @@ impl Map {
             rooms: Vec::new(),
             width,
             height,
+            depth,
+            revision: NEXT_REVISION.fetch_add(1, Ordering::Relaxed),
             revealed_tiles: vec![false; vec_size],
             visible_tiles: vec![false; vec_size],
             blocked: vec![false; vec_size],
+            trapped: vec![false; vec_size],
             tile_content: vec![Vec::new(); vec_size],
+            bloodstains: HashSet::new(),
+            light: vec![RGB::from_f32(0.0, 0.0, 0.0); vec_size],
+            remembered: HashMap::new(),
         };
 
         let mut rng = RandomNumberGenerator::new();
@@ -129,6 +192,10 @@ impl Map {
             }
         }
 
+        let stairs_position = map.rooms[map.rooms.len() - 1].center();
+        let stairs_idx = map.point2d_to_index(stairs_position);
+        map.tiles[stairs_idx] = TileType::DownStairs;
+
         map
     }
 }
@@ -151,16 +218,16 @@ impl BaseMap for Map {
         let w = self.width as usize;
 
         // Cardinal directions
-        if self.is_exit_valid(x - 1, y) { exits.push((idx - 1, 1.0)) };
-        if self.is_exit_valid(x + 1, y) { exits.push((idx + 1, 1.0)) };
-        if self.is_exit_valid(x, y - 1) { exits.push((idx - w, 1.0)) };
-        if self.is_exit_valid(x, y + 1) { exits.push((idx + w, 1.0)) };
+        if self.is_exit_valid(x - 1, y) { exits.push((idx - 1, self.exit_cost(idx - 1, 1.0))) };
+        if self.is_exit_valid(x + 1, y) { exits.push((idx + 1, self.exit_cost(idx + 1, 1.0))) };
+        if self.is_exit_valid(x, y - 1) { exits.push((idx - w, self.exit_cost(idx - w, 1.0))) };
+        if self.is_exit_valid(x, y + 1) { exits.push((idx + w, self.exit_cost(idx + w, 1.0))) };
 
         // Diagonals
-        if self.is_exit_valid(x - 1, y - 1) { exits.push(((idx - w) - 1, 1.45)); }
-        if self.is_exit_valid(x + 1, y - 1) { exits.push(((idx - w) + 1, 1.45)); }
-        if self.is_exit_valid(x - 1, y + 1) { exits.push(((idx + w) - 1, 1.45)); }
-        if self.is_exit_valid(x + 1, y + 1) { exits.push(((idx + w) + 1, 1.45)); }
+        if self.is_exit_valid(x - 1, y - 1) { exits.push(((idx - w) - 1, self.exit_cost((idx - w) - 1, 1.45))); }
+        if self.is_exit_valid(x + 1, y - 1) { exits.push(((idx - w) + 1, self.exit_cost((idx - w) + 1, 1.45))); }
+        if self.is_exit_valid(x - 1, y + 1) { exits.push(((idx + w) - 1, self.exit_cost((idx + w) - 1, 1.45))); }
+        if self.is_exit_valid(x + 1, y + 1) { exits.push(((idx + w) + 1, self.exit_cost((idx + w) + 1, 1.45))); }
 
         exits
     }
@@ -172,35 +239,54 @@ impl Algorithm2D for Map {
     }
 }
 
+/// Even unlit tiles in view keep this much light, so the player can make out the shape of a dark room.
+const MIN_LIGHT: f32 = 0.15;
+const REMEMBERED_SHADE: f32 = 0.35;
+const FLOOR_SPRITE: FontCharType = 0;
+const WALL_SPRITE: FontCharType = 8;
+
 pub fn draw_map(ecs: &World, ctx: &mut BTerm) {
     let mut draw_batch = DrawBatch::new();
     let map = ecs.fetch::<Map>();
-    let positions = ecs.read_storage::<Position>();
-    let players = ecs.read_storage::<Player>();
+    let mode = *ecs.fetch::<GraphicsMode>();
 
     for (idx, tile) in map.tiles.iter().enumerate() {
         let map_point = map.index_to_point2d(idx);
         if map.revealed_tiles[idx] {
-            let (glyph, mut fg) = match tile {
-                TileType::Floor => (to_cp437('.'), RGB::named(GRAY).to_rgba(1.0)),
-                TileType::Wall => (to_cp437('#'), RGB::named(GREEN).to_rgba(1.0)),
+            let bloodstained = map.bloodstains.contains(&idx);
+            let mut bg = if bloodstained { RGB::from_f32(0.5, 0.0, 0.0).to_rgba(1.0) } else { RGB::named(BLACK).to_rgba(1.0) };
+            let (glyph, sprite, base) = match tile {
+                TileType::Floor => (to_cp437('.'), Some(FLOOR_SPRITE), RGB::named(GRAY)),
+                TileType::Wall => (to_cp437('#'), Some(WALL_SPRITE), RGB::named(GREEN)),
+                TileType::DownStairs => (to_cp437('>'), None, RGB::named(CYAN)),
             };
 
-            if !map.visible_tiles[idx] {
-                fg = RGB::named(GRAY).to_rgba(0.5);
-            } else {
-                for (pos, _) in (&positions, &players).join() {
-                    let distance = 1.0
-                        - (DistanceAlg::Pythagoras.distance2d(map_point, Point::new(pos.x, pos.y))
-                        / 10.0);
-                    fg = fg * distance;
+            if let Some(sprite) = mode.sprite(sprite) {
+                let mut shade = if map.visible_tiles[idx] {
+                    map.light_at(idx)
+                } else {
+                    RGB::from_f32(REMEMBERED_SHADE, REMEMBERED_SHADE, REMEMBERED_SHADE)
+                };
+                // No background under a sprite, so blood shows as a red tint instead
+                if bloodstained {
+                    shade.g *= 0.4;
+                    shade.b *= 0.4;
                 }
+                draw_batch.target(RenderLayer::Map.console(true)).set(map_point, sprite_colours(shade), sprite);
+                continue;
             }
 
-            draw_batch.set(map_point, ColorPair::new(fg, BLACK), glyph);
+            let fg = if !map.visible_tiles[idx] {
+                // Bloodstains stay in memory, just darker
+                if bloodstained { bg = RGB::from_f32(0.25, 0.0, 0.0).to_rgba(1.0); }
+                RGB::named(GRAY).to_rgba(0.5)
+            } else {
+                (base * map.light_at(idx)).to_rgba(1.0)
+            };
+
+            draw_batch.target(RenderLayer::Map.console(false)).set(map_point, ColorPair::new(fg, bg), glyph);
         }
     }
 
-    draw_batch.submit(0).expect("Batch error");
-    render_draw_buffer(ctx).expect("Render error");
+    submit(&mut draw_batch, ctx);
 }
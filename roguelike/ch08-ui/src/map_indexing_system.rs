@@ -5,10 +5,10 @@ use super::prelude::*;
 pub struct MapIndexingSystem {}
 
 impl<'a> System<'a> for MapIndexingSystem {
-    type SystemData = (WriteExpect<'a, Map>, ReadStorage<'a, Position>, ReadStorage<'a, BlocksTile>, Entities<'a>, );
+    type SystemData = (WriteExpect<'a, Map>, ReadStorage<'a, Position>, ReadStorage<'a, BlocksTile>, ReadStorage<'a, EntryTrigger>, Entities<'a>, );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, position, blockers, triggers, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
@@ -19,6 +19,9 @@ impl<'a> System<'a> for MapIndexingSystem {
             if blockers.get(entity).is_some() {
                 map.blocked[idx] = true;
             }
+            if triggers.get(entity).is_some() {
+                map.trapped[idx] = true;
+            }
             map.tile_content[idx].push(entity);
         }
     }
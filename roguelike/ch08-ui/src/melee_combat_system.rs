@@ -2,34 +2,55 @@ use specs::prelude::*;
 use bracket_lib::prelude::*;
 use super::prelude::*;
 
+const COMBAT_NOISE: i32 = 10;
+
 pub struct MeleeCombatSystem {}
 
 impl<'a> System<'a> for MeleeCombatSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (Entities<'a>,
+                       ReadExpect<'a, Entity>,
                        WriteExpect<'a, GameLog>,
                        WriteStorage<'a, WantsToMelee>,
                        ReadStorage<'a, Name>,
                        ReadStorage<'a, CombatStats>,
-                       WriteStorage<'a, SufferDamage>
+                       WriteStorage<'a, SufferDamage>,
+                       WriteStorage<'a, MakesNoise>,
+                       WriteExpect<'a, ParticleBuilder>,
+                       ReadStorage<'a, Position>
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities,mut log, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
+        let (entities, player_entity, mut log, mut wants_melee, names, combat_stats, mut inflict_damage, mut noises, mut particles, positions) = data;
 
-        for (_entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+        for (entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
             if stats.hp > 0 {
                 let target_stats = combat_stats.get(wants_melee.target).unwrap();
                 if target_stats.hp > 0 {
+                    MakesNoise::new_noise(&mut noises, entity, COMBAT_NOISE);
                     let target_name = names.get(wants_melee.target).unwrap();
 
                     let damage = i32::max(0, stats.power - target_stats.defense);
 
                     if damage == 0 {
                         //log(&format!("{} is unable to hurt {}", &name.name, &target_name.name));
-                        log.entries.push(format!("{} is unable to hurt {}", &name.name, &target_name.name));
+                        log.add(LogBuilder::combat()
+                            .actor(&name.name, entity == *player_entity)
+                            .text("is unable to hurt")
+                            .actor(&target_name.name, wants_melee.target == *player_entity)
+                            .text("."));
                     } else {
-                        log.entries.push(format!("{} hits {}, -{}hp.", &name.name, &target_name.name, damage));
+                        log.add(LogBuilder::combat()
+                            .actor(&name.name, entity == *player_entity)
+                            .text("hits")
+                            .actor(&target_name.name, wants_melee.target == *player_entity)
+                            .text(",")
+                            .damage(damage)
+                            .text("."));
                         SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                        if let Some(pos) = positions.get(wants_melee.target) {
+                            particles.request(pos.x, pos.y, RGB::named(ORANGE), RGB::named(BLACK), to_cp437('‼'), 200.0);
+                        }
                     }
                 }
             }
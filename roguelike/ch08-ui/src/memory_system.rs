@@ -0,0 +1,45 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+/// Keeps `Map::remembered` up to date: whatever is in view is remembered where it stands,
+/// and memories of tiles back in view are dropped if the entity has gone or moved on.
+pub struct EntityMemorySystem {}
+
+impl<'a> System<'a> for EntityMemorySystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        Entities<'a>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Renderable>,
+                        ReadStorage<'a, Player>,
+                        ReadStorage<'a, Hidden>,
+                        ReadStorage<'a, ParticleLifetime>,
+                        ReadStorage<'a, CombatStats> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, entities, positions, renderables, players, hidden, particles, combat_stats) = data;
+
+        // Anything still in view gets remembered again below
+        let stale: Vec<Entity> = map.remembered.iter()
+            .filter(|(_, memory)| map.visible_tiles[map.point2d_to_index(memory.point)])
+            .map(|(entity, _)| *entity)
+            .collect();
+        for entity in stale {
+            map.remembered.remove(&entity);
+        }
+
+        for (entity, pos, render, _, _, _) in (&entities, &positions, &renderables, !&players, !&hidden, !&particles).join() {
+            let point = Point::new(pos.x, pos.y);
+            if map.visible_tiles[map.point2d_to_index(point)] {
+                map.remembered.insert(entity, RememberedEntity {
+                    point,
+                    glyph: render.glyph,
+                    sprite: render.sprite,
+                    fg: render.fg,
+                    layer: RenderLayer::of(entity, &combat_stats),
+                    render_order: render.render_order,
+                });
+            }
+        }
+    }
+}
@@ -1,50 +1,293 @@
+use std::collections::{HashMap, VecDeque};
 use super::prelude::*;
 use crate::components::Position;
 use bracket_lib::prelude::*;
 use specs::prelude::*;
 
+const MEMORY_TURNS: i32 = 20;
+const SEARCH_RADIUS: i32 = 3;
+/// Ranged monsters back off when their target gets closer than this.
+const KEEP_DISTANCE: f32 = 3.0;
+
 pub struct MonsterAI {}
 
+/// Moves one step to `destination` and keeps the blocking map in sync. Returns false if already there.
+fn move_to(map: &mut Map, pos: &mut Position, viewshed: &mut Viewshed, destination: usize) -> bool {
+    let mut idx = map.point2d_to_index(Point::new(pos.x, pos.y));
+    if idx == destination { return false; }
+    map.blocked[idx] = false;
+
+    let point = map.index_to_point2d(destination);
+    (pos.x, pos.y) = (point.x, point.y);
+
+    idx = map.point2d_to_index(Point::new(pos.x, pos.y));
+    map.blocked[idx] = true;
+    viewshed.dirty = true;
+    true
+}
+
+/// The next step towards `to`. Follows the path found last time while it leads there and its next step is open.
+fn path_step(map: &Map, path: &mut CachedPath, from: usize, to: usize) -> Option<usize> {
+    let still_open = path.destination == to && path.steps.front()
+        .is_some_and(|next| map.get_available_exits(from).iter().any(|(exit, _)| exit == next));
+    if !still_open {
+        let found = a_star_search(from as i32, to as i32, map);
+        path.destination = to;
+        path.steps = if found.success { found.steps.into_iter().skip(1).collect() } else { VecDeque::new() };
+    }
+    path.steps.pop_front()
+}
+
+/// Greedily picks the neighbouring tile furthest from `threat`, if it is further than where we stand.
+fn step_away(map: &Map, from: usize, threat: Point) -> Option<usize> {
+    let current = DistanceAlg::Pythagoras.distance2d(map.index_to_point2d(from), threat);
+    map.get_available_exits(from)
+        .into_iter()
+        .map(|(exit, _)| (exit, DistanceAlg::Pythagoras.distance2d(map.index_to_point2d(exit), threat)))
+        .filter(|(_, distance)| *distance > current)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(exit, _)| exit)
+}
+
+/// Switches to `new_state`, logging the transition in debug builds.
+fn transition(state: &mut AiState, new_state: AiState, name: Option<&Name>) {
+    if *state == new_state { return; }
+    if cfg!(debug_assertions) {
+        let name = name.map_or("?", |name| name.name.as_str());
+        log(format!("{}: {} -> {}", name, state.describe(), new_state.describe()));
+    }
+    *state = new_state;
+}
+
+/// Picks a random open tile around the spot where the target was last seen.
+fn search_destination(last_seen: Point, rng: &mut RandomNumberGenerator, map: &Map) -> Option<usize> {
+    for _ in 0..10 {
+        let point = Point::new(
+            last_seen.x + rng.range(-SEARCH_RADIUS, SEARCH_RADIUS + 1),
+            last_seen.y + rng.range(-SEARCH_RADIUS, SEARCH_RADIUS + 1),
+        );
+        if map.in_bounds(point) && !map.blocked[map.point2d_to_index(point)] {
+            return Some(map.point2d_to_index(point));
+        }
+    }
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
+fn next_state(state: AiState, idx: usize, target: Option<Entity>, threat: Option<Entity>, badly_hurt: bool, wanders: bool, memory: &mut TargetMemory, rng: &mut RandomNumberGenerator, map: &Map) -> AiState {
+    if let Some(threat) = threat {
+        return AiState::Flee { from: threat };
+    }
+    if let Some(target) = target {
+        return if badly_hurt { AiState::Flee { from: target } } else { AiState::Chase { target } };
+    }
+
+    if memory.last_seen.is_some() {
+        memory.turns_since_seen += 1;
+        if memory.turns_since_seen > MEMORY_TURNS {
+            *memory = TargetMemory::default();
+        }
+    }
+
+    match (state, memory.last_seen) {
+        (AiState::Chase { .. }, Some(last_seen)) => AiState::Search { destination: map.point2d_to_index(last_seen) },
+        (AiState::Search { .. }, None) | (AiState::Chase { .. }, None) | (AiState::Flee { .. }, _) => AiState::Idle,
+        (AiState::Search { destination }, Some(last_seen)) if destination == idx => {
+            search_destination(last_seen, rng, map).map_or(AiState::Idle, |destination| AiState::Search { destination })
+        }
+        (AiState::Wander { destination }, _) if destination == idx => AiState::Idle,
+        (AiState::Idle, _) if wanders && rng.roll_dice(1, 10) == 1 && !map.rooms.is_empty() => {
+            let room = &map.rooms[rng.range(0, map.rooms.len())];
+            AiState::Wander { destination: map.point2d_to_index(room.center()) }
+        }
+        _ => state,
+    }
+}
+
 impl<'a> System<'a> for MonsterAI {
     #[allow(clippy::type_complexity)]
     type SystemData = ( WriteExpect<'a, Map>,
                         ReadExpect<'a, Point>,
                         ReadExpect<'a, Entity>,
                         ReadExpect<'a, RunState>,
+                        ReadExpect<'a, AiMaps>,
+                        ReadExpect<'a, FactionTable>,
+                        WriteExpect<'a, RandomNumberGenerator>,
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         ReadStorage<'a, Monster>,
+                        ReadStorage<'a, Companion>,
+                        WriteStorage<'a, AiState>,
+                        WriteStorage<'a, TargetMemory>,
+                        WriteStorage<'a, CachedPath>,
+                        WriteStorage<'a, HeardNoise>,
+                        ReadStorage<'a, Faction>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, CombatStats>,
+                        ReadStorage<'a, RangedWeapon>,
+                        (ReadStorage<'a, Item>, ReadStorage<'a, Corpse>, WriteStorage<'a, WantsToPickupItem>),
                         WriteStorage<'a, Position>,
-                        WriteStorage<'a, WantsToMelee>);
+                        WriteStorage<'a, WantsToMelee>,
+                        WriteStorage<'a, WantsToShoot>,
+                        WriteStorage<'a, Confusion>,
+                        WriteStorage<'a, EntityMoved>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, player_pos, player_entity, runstate, entities, mut viewshed, monster, mut position, mut wants_to_melee) = data;
+        let (mut map, player_pos, player_entity, runstate, ai_maps, faction_table, mut rng, entities, mut viewshed, monster, companions, mut ai_state,
+            mut memories, mut paths, mut heard, factions, names, combat_stats, weapons, (items, corpses, mut wants_pickup), mut position, mut wants_to_melee, mut wants_to_shoot,
+            mut confusion, mut moved) = data;
 
         if *runstate != RunState::MonsterTurn { return; }
 
-        for (entity, mut viewshed,_monster,mut pos) in (&entities, &mut viewshed, &monster, &mut position).join() {
-            let distance = DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
-            if distance < 1.5 {
-                wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to insert attack");
-            } else if viewshed.visible_tiles.contains(&*player_pos) {
-                let path = a_star_search(
-                    map.point2d_to_index(Point::new(pos.x, pos.y)) as i32,
-                    map.point2d_to_index(Point::new(player_pos.x, player_pos.y)) as i32,
-                    &mut *map,
-                );
+        // Everyone who could be a target or a threat, and where they stand
+        let mut locations: HashMap<Entity, Point> = (&entities, &position, &combat_stats, &factions).join()
+            .filter(|(_, _, stats, _)| stats.hp > 0)
+            .map(|(entity, pos, _, _)| (entity, Point::new(pos.x, pos.y)))
+            .collect();
+        // Shared by everyone chasing the same target this turn
+        let mut chase_maps: HashMap<Entity, DijkstraMap> = HashMap::new();
+        // Items worth picking up, by where they lie
+        let mut item_spots: HashMap<usize, Entity> = (&entities, &items, &position, !&corpses).join()
+            .map(|(item, _item, pos, _)| (map.point2d_to_index(Point::new(pos.x, pos.y)), item))
+            .collect();
+
+        for (entity, viewshed, _monster, state, memory, pos, faction) in (&entities, &mut viewshed, &monster, &mut ai_state, &mut memories, &mut position, &factions).join() {
+            // Killed earlier this turn, but not cleaned up until the turn is over
+            if combat_stats.get(entity).is_some_and(|stats| stats.hp <= 0) { continue; }
+
+            // Confused monsters lose their turn
+            if let Some(confused) = confusion.get_mut(entity) {
+                confused.turns -= 1;
+                if confused.turns < 1 {
+                    confusion.remove(entity);
+                }
+                continue;
+            }
 
-                if path.success && path.steps.len() > 1 {
-                    let mut idx = map.point2d_to_index(Point::new(pos.x, pos.y));
-                    map.blocked[idx] = false;
+            let my_point = Point::new(pos.x, pos.y);
+            let badly_hurt = combat_stats.get(entity).is_some_and(|stats| stats.hp * 4 <= stats.max_hp);
 
-                    let point = map.index_to_point2d(path.steps[1]);
-                    (pos.x, pos.y) = (point.x, point.y);
+            // The nearest visible hostile to attack, and the nearest visible thing to run from
+            let mut target: Option<(Entity, f32)> = None;
+            let mut threat: Option<(Entity, f32)> = None;
+            for (other, other_point) in locations.iter() {
+                if *other == entity || !viewshed.can_see(map.point2d_to_index(*other_point)) { continue; }
+                let Some(other_faction) = factions.get(*other) else { continue; };
+                let distance = DistanceAlg::Pythagoras.distance2d(my_point, *other_point);
+                let nearest = match faction_table.reaction(&faction.name, &other_faction.name) {
+                    Reaction::Attack => &mut target,
+                    Reaction::Flee => &mut threat,
+                    Reaction::Ignore => continue,
+                };
+                if nearest.is_none_or(|(_, best)| distance < best) {
+                    *nearest = Some((*other, distance));
+                }
+            }
+            let target = target.map(|(target, _)| target);
+            let threat = threat.map(|(threat, _)| threat);
+            if let Some(seen) = target.or(threat) {
+                memory.last_seen = Some(locations[&seen]);
+                memory.turns_since_seen = 0;
+            }
 
-                    idx = map.point2d_to_index(Point::new(pos.x, pos.y));
-                    map.blocked[idx] = true;
-                    viewshed.dirty = true;
+            let idx = map.point2d_to_index(my_point);
+            if let Some(noise) = heard.get(entity) {
+                // Go and investigate anything heard, unless already busy
+                if target.is_none() && threat.is_none() && !matches!(*state, AiState::Chase { .. } | AiState::Flee { .. }) {
+                    memory.last_seen = Some(noise.origin);
+                    memory.turns_since_seen = 0;
+                    transition(state, AiState::Search { destination: map.point2d_to_index(noise.origin) }, names.get(entity));
                 }
             }
+            let companion = companions.get(entity);
+            let new_state = next_state(*state, idx, target, threat, badly_hurt, companion.is_none(), memory, &mut rng, &map);
+            transition(state, new_state, names.get(entity));
+
+            match *state {
+                AiState::Chase { target } => {
+                    let target_point = locations[&target];
+                    let distance = DistanceAlg::Pythagoras.distance2d(my_point, target_point);
+                    let can_shoot = weapons.get(entity).is_some_and(|weapon| distance <= weapon.range as f32)
+                        && map.has_line_of_sight(my_point, target_point);
+
+                    if can_shoot {
+                        // Keep some distance, then shoot
+                        let backed_off = distance < KEEP_DISTANCE && step_away(&map, idx, target_point)
+                            .is_some_and(|step| move_to(&mut map, pos, viewshed, step));
+                        if !backed_off {
+                            wants_to_shoot.insert(entity, WantsToShoot{ target }).expect("Unable to insert attack");
+                        }
+                    } else if distance < 1.5 {
+                        wants_to_melee.insert(entity, WantsToMelee{ target }).expect("Unable to insert attack");
+                    } else {
+                        let step = if target == *player_entity {
+                            downhill_exit(&ai_maps.approach, idx, &map)
+                        } else {
+                            let target_idx = map.point2d_to_index(target_point);
+                            let dm = chase_maps.entry(target).or_insert_with(|| approach_map(&map, target_idx));
+                            downhill_exit(dm, idx, &map)
+                        };
+                        if let Some(step) = step {
+                            move_to(&mut map, pos, viewshed, step);
+                        }
+                    }
+                }
+                AiState::Flee { from } => {
+                    let threat_point = locations[&from];
+                    let step = if from == *player_entity {
+                        downhill_exit(&ai_maps.flee, idx, &map)
+                    } else {
+                        step_away(&map, idx, threat_point)
+                    };
+                    let fled = step.is_some_and(|step| move_to(&mut map, pos, viewshed, step));
+                    if !fled && DistanceAlg::Pythagoras.distance2d(my_point, threat_point) < 1.5 {
+                        // Cornered, so fight back
+                        wants_to_melee.insert(entity, WantsToMelee{ target: from }).expect("Unable to insert attack");
+                    }
+                }
+                AiState::Search { destination } | AiState::Wander { destination } => {
+                    let path = paths.entry(entity).expect("Unable to cache path").or_insert_with(CachedPath::default);
+                    match path_step(&map, path, idx, destination) {
+                        Some(step) => { move_to(&mut map, pos, viewshed, step); }
+                        None => {
+                            // Can't get there: keep looking around while the memory lasts
+                            let retry = memory.last_seen
+                                .filter(|_| matches!(state, AiState::Search { .. }))
+                                .and_then(|last_seen| search_destination(last_seen, &mut rng, &map));
+                            let new_state = retry.map_or(AiState::Idle, |destination| AiState::Search { destination });
+                            transition(state, new_state, names.get(entity));
+                        }
+                    }
+                }
+                AiState::Idle if companion.is_some_and(|companion| DistanceAlg::Pythagoras.distance2d(my_point, *player_pos) > companion.leash as f32) => {
+                    // Catch up with the player
+                    if let Some(step) = downhill_exit(&ai_maps.approach, idx, &map) {
+                        move_to(&mut map, pos, viewshed, step);
+                    }
+                }
+                AiState::Idle if companion.is_none() => {
+                    let sees_item = viewshed.visible_tiles.iter().any(|tile| ai_maps.items.map[map.point2d_to_index(*tile)] == 0.0);
+                    if let Some(item) = item_spots.remove(&idx) {
+                        // Made it to something, so take it
+                        wants_pickup.insert(entity, WantsToPickupItem{ collected_by: entity, item }).expect("Unable to insert want to pickup");
+                    } else if sees_item {
+                        if let Some(step) = downhill_exit(&ai_maps.items, idx, &map) {
+                            move_to(&mut map, pos, viewshed, step);
+                        }
+                    }
+                }
+                // A companion near the player stays put and leaves the loot to them
+                AiState::Idle => {}
+            }
+
+            if my_point != Point::new(pos.x, pos.y) {
+                moved.insert(entity, EntityMoved{}).expect("Unable to insert marker");
+            }
+            if let Some(location) = locations.get_mut(&entity) {
+                *location = Point::new(pos.x, pos.y);
+            }
         }
+
+        heard.clear();
     }
 }
@@ -0,0 +1,71 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+/// Extra distance a sound loses for every wall tile it passes through.
+const WALL_DAMPING: i32 = 4;
+
+/// Spreads a sound from `origin` and returns the distance it travelled to every tile it reached.
+fn propagate(map: &Map, origin: Point, volume: i32) -> HashMap<usize, i32> {
+    let mut reached: HashMap<usize, i32> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    let start = map.point2d_to_index(origin);
+    reached.insert(start, 0);
+    open.push(Reverse((0, start)));
+
+    while let Some(Reverse((cost, idx))) = open.pop() {
+        if cost > reached[&idx] { continue; }
+        let point = map.index_to_point2d(idx);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let next = Point::new(point.x + dx, point.y + dy);
+                if (dx == 0 && dy == 0) || !map.in_bounds(next) { continue; }
+
+                let next_idx = map.point2d_to_index(next);
+                let step = if map.tiles[next_idx] == TileType::Wall { WALL_DAMPING } else { 1 };
+                let next_cost = cost + step;
+                if next_cost <= volume && reached.get(&next_idx).is_none_or(|known| next_cost < *known) {
+                    reached.insert(next_idx, next_cost);
+                    open.push(Reverse((next_cost, next_idx)));
+                }
+            }
+        }
+    }
+
+    reached
+}
+
+pub struct NoiseSystem {}
+
+impl<'a> System<'a> for NoiseSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( ReadExpect<'a, Map>,
+                        Entities<'a>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Hearing>,
+                        WriteStorage<'a, MakesNoise>,
+                        WriteStorage<'a, HeardNoise> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, entities, positions, hearing, mut noises, mut heard) = data;
+
+        for (source, noise, pos) in (&entities, &noises, &positions).join() {
+            let origin = Point::new(pos.x, pos.y);
+            let volume = noise.volume.iter().copied().max().unwrap_or(0);
+            let reached = propagate(&map, origin, volume);
+
+            for (listener, hearing, listener_pos) in (&entities, &hearing, &positions).join() {
+                if listener == source { continue; }
+                let listener_point = Point::new(listener_pos.x, listener_pos.y);
+                let in_earshot = DistanceAlg::Pythagoras.distance2d(listener_point, origin) <= hearing.range as f32;
+                if in_earshot && reached.contains_key(&map.point2d_to_index(listener_point)) {
+                    heard.insert(listener, HeardNoise { origin }).expect("Unable to insert noise");
+                }
+            }
+        }
+
+        noises.clear();
+    }
+}
@@ -0,0 +1,68 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+struct ParticleRequest {
+    x: i32,
+    y: i32,
+    fg: RGB,
+    bg: RGB,
+    glyph: FontCharType,
+    lifetime: f32,
+}
+
+/// Queue of particles for `ParticleSpawnSystem` to create. Systems push here instead of building entities.
+#[derive(Default)]
+pub struct ParticleBuilder {
+    requests: Vec<ParticleRequest>,
+}
+
+impl ParticleBuilder {
+    pub fn new() -> ParticleBuilder {
+        ParticleBuilder::default()
+    }
+
+    pub fn request(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: FontCharType, lifetime: f32) {
+        self.requests.push(ParticleRequest { x, y, fg, bg, glyph, lifetime });
+    }
+}
+
+pub struct ParticleSpawnSystem {}
+
+impl<'a> System<'a> for ParticleSpawnSystem {
+    type SystemData = ( Entities<'a>,
+                        WriteExpect<'a, ParticleBuilder>,
+                        WriteStorage<'a, Position>,
+                        WriteStorage<'a, Renderable>,
+                        WriteStorage<'a, ParticleLifetime> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut builder, mut positions, mut renderables, mut particles) = data;
+
+        for request in builder.requests.drain(..) {
+            let particle = entities.create();
+            positions.insert(particle, Position { x: request.x, y: request.y }).expect("Unable to insert position");
+            renderables.insert(particle, Renderable { glyph: request.glyph, sprite: None, fg: request.fg, bg: request.bg, render_order: 0 }).expect("Unable to insert renderable");
+            particles.insert(particle, ParticleLifetime { lifetime_ms: request.lifetime }).expect("Unable to insert lifetime");
+        }
+    }
+}
+
+/// Ages every particle by the time the last frame took and deletes the ones that have run out.
+pub fn cull_dead_particles(ecs: &mut World, ctx: &BTerm) {
+    let mut dead: Vec<Entity> = Vec::new();
+    {
+        let mut particles = ecs.write_storage::<ParticleLifetime>();
+        let entities = ecs.entities();
+        for (entity, particle) in (&entities, &mut particles).join() {
+            particle.lifetime_ms -= ctx.frame_time_ms;
+            if particle.lifetime_ms < 0.0 {
+                dead.push(entity);
+            }
+        }
+    }
+
+    for particle in dead {
+        ecs.delete_entity(particle).expect("Particle will not die");
+    }
+}
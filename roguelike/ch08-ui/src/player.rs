@@ -3,66 +3,303 @@ use specs::prelude::*;
 use std::cmp::{max, min};
 use super::prelude::*;
 
+const FOOTSTEP_NOISE: i32 = 2;
+/// How far around the player a deliberate search reaches.
+const SEARCH_RANGE: f32 = 3.0;
+
+/// Counts down the player's confusion, if any. Returns true while they are still confused.
+fn tick_confusion(ecs: &mut World) -> bool {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut confusion = ecs.write_storage::<Confusion>();
+    let Some(confused) = confusion.get_mut(player_entity) else { return false; };
+    confused.turns -= 1;
+    if confused.turns < 1 {
+        confusion.remove(player_entity);
+        ecs.write_resource::<GameLog>().add(LogBuilder::system().text("You feel less confused."));
+    }
+    true
+}
 
 pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+    // Confused players stumble in a random direction, but never stay put
+    let (delta_x, delta_y) = if tick_confusion(ecs) {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        loop {
+            let step = (rng.range(-1, 2), rng.range(-1, 2));
+            if step != (0, 0) { break step; }
+        }
+    } else {
+        (delta_x, delta_y)
+    };
+
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let entities = ecs.entities();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let combat_stats = ecs.read_storage::<CombatStats>();
+    let factions = ecs.read_storage::<Faction>();
+    let faction_table = ecs.fetch::<FactionTable>();
     let map = ecs.fetch::<Map>();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let mut noises = ecs.write_storage::<MakesNoise>();
+    let mut moved = ecs.write_storage::<EntityMoved>();
+    let mut swap_with: Option<(Entity, Point)> = None;
 
     for (entity, _player, pos, viewshed) in (&entities, &mut players, &mut positions, &mut viewsheds).join() {
         let destination_idx = map.point2d_to_index(Point::new(pos.x + delta_x, pos.y + delta_y));
 
-        for potential_target in map.tile_content[destination_idx].iter() {
+        for potential_target in map.tile_content[destination_idx].iter().filter(|other| **other != entity) {
             let target = combat_stats.get(*potential_target);
             if let Some(_target) = target {
-                wants_to_melee.insert(entity, WantsToMelee{ target: *potential_target }).expect("Add target failed");
-                return;
+                let friendly = match (factions.get(entity), factions.get(*potential_target)) {
+                    (Some(mine), Some(theirs)) => faction_table.reaction(&mine.name, &theirs.name) != Reaction::Attack,
+                    _ => false
+                };
+                if !friendly {
+                    wants_to_melee.insert(entity, WantsToMelee{ target: *potential_target }).expect("Add target failed");
+                    return;
+                }
+                // Friends step aside into the tile we are leaving
+                swap_with = Some((*potential_target, Point::new(pos.x, pos.y)));
             }
         }
 
 
-        if !map.blocked[destination_idx] {
+        if !map.blocked[destination_idx] || swap_with.is_some() {
             pos.x = min(map.width - 1, max(0, pos.x + delta_x));
             pos.y = min(map.height - 1, max(0, pos.y + delta_y));
             viewshed.dirty = true;
+            MakesNoise::new_noise(&mut noises, entity, FOOTSTEP_NOISE);
+            moved.insert(entity, EntityMoved{}).expect("Unable to insert marker");
 
             let mut ppos = ecs.write_resource::<Point>();
             ppos.x = pos.x;
             ppos.y = pos.y;
         }
     }
+
+    if let Some((friend, vacated)) = swap_with {
+        if let Some(friend_pos) = positions.get_mut(friend) {
+            friend_pos.x = vacated.x;
+            friend_pos.y = vacated.y;
+        }
+        if let Some(friend_viewshed) = viewsheds.get_mut(friend) {
+            friend_viewshed.dirty = true;
+        }
+        moved.insert(friend, EntityMoved{}).expect("Unable to insert marker");
+    }
 }
 
+fn try_next_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let player_idx = map.point2d_to_index(*player_pos);
+    if map.tiles[player_idx] == TileType::DownStairs {
+        true
+    } else {
+        ecs.write_resource::<GameLog>().add(LogBuilder::system().text("There is no way down from here."));
+        false
+    }
+}
 
-pub fn player_input(ecs: &mut World, ctx: &mut BTerm) -> RunState {
-    match ctx.key {
-        None => return RunState::AwaitingInput,
-        Some(key) => match key {
-            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => try_move_player(-1, 0, ecs),
-            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => try_move_player(1, 0, ecs),
-            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => try_move_player(0, -1, ecs),
-            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => try_move_player(0, 1, ecs),
-            VirtualKeyCode::S => ctx.screenshot("roguelike.png"),
 
-            // Diagonals
-            VirtualKeyCode::Numpad9 |
-            VirtualKeyCode::Y => try_move_player(1, -1, ecs),
+/// Queues a shot at whatever stands on `target`. Returns false if there is nothing to shoot there.
+pub fn fire_at(ecs: &mut World, target: Point) -> bool {
+    let map = ecs.fetch::<Map>();
+    let player_entity = ecs.fetch::<Entity>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let mut wants_to_shoot = ecs.write_storage::<WantsToShoot>();
 
-            VirtualKeyCode::Numpad7 |
-            VirtualKeyCode::U => try_move_player(-1, -1, ecs),
+    let idx = map.point2d_to_index(target);
+    let victim = map.tile_content[idx].iter()
+        .find(|entity| combat_stats.get(**entity).is_some() && player_reaction_to(ecs, **entity) == Reaction::Attack);
+    match victim {
+        Some(victim) => {
+            wants_to_shoot.insert(*player_entity, WantsToShoot{ target: *victim }).expect("Add target failed");
+            true
+        }
+        None => false
+    }
+}
+
+fn get_item(ecs: &mut World) {
+    let player_pos = ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
 
-            VirtualKeyCode::Numpad3 |
-            VirtualKeyCode::N => try_move_player(1, 1, ecs),
+    let target_item = (&entities, &items, &positions).join()
+        .find(|(_, _, position)| position.x == player_pos.x && position.y == player_pos.y)
+        .map(|(item_entity, _, _)| item_entity);
 
-            VirtualKeyCode::Numpad1 |
-            VirtualKeyCode::B => try_move_player(-1, 1, ecs),
+    match target_item {
+        None => gamelog.add(LogBuilder::loot().text("There is nothing here to pick up.")),
+        Some(item) => {
+            let mut pickup = ecs.write_storage::<WantsToPickupItem>();
+            pickup.insert(*player_entity, WantsToPickupItem{ collected_by: *player_entity, item }).expect("Unable to insert want to pickup");
+        }
+    }
+}
 
-            _ => return RunState::AwaitingInput
+fn start_targeting(ecs: &mut World) -> RunState {
+    let player_entity = ecs.fetch::<Entity>();
+    let weapons = ecs.read_storage::<RangedWeapon>();
+    match weapons.get(*player_entity) {
+        Some(weapon) => RunState::ShowTargeting { range: weapon.range },
+        None => {
+            ecs.write_resource::<GameLog>().add(LogBuilder::combat().text("You have nothing to shoot with."));
+            RunState::AwaitingInput
+        }
+    }
+}
+
+/// True when something that wants the player dead is in the player's viewshed.
+/// How the player's faction treats `other`'s. Anything without a faction is left alone.
+pub fn player_reaction_to(ecs: &World, other: Entity) -> Reaction {
+    let player_entity = ecs.fetch::<Entity>();
+    let factions = ecs.read_storage::<Faction>();
+    let faction_table = ecs.fetch::<FactionTable>();
+    match (factions.get(*player_entity), factions.get(other)) {
+        (Some(mine), Some(theirs)) => faction_table.reaction(&mine.name, &theirs.name),
+        _ => Reaction::Ignore,
+    }
+}
+
+pub fn hostile_in_view(ecs: &World) -> bool {
+    let player_entity = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let factions = ecs.read_storage::<Faction>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let faction_table = ecs.fetch::<FactionTable>();
+
+    let (Some(viewshed), Some(mine)) = (viewsheds.get(*player_entity), factions.get(*player_entity)) else { return false; };
+    viewshed.visible_tiles.iter()
+        .flat_map(|tile| map.tile_content[map.point2d_to_index(*tile)].iter())
+        .filter(|entity| combat_stats.get(**entity).is_some_and(|stats| stats.hp > 0))
+        .filter_map(|entity| factions.get(*entity))
+        .any(|theirs| faction_table.reaction(&theirs.name, &mine.name) == Reaction::Attack)
+}
+
+/// Passes a turn. Waiting with no enemies around restores a little HP, more for tougher characters.
+pub fn skip_turn(ecs: &mut World) -> RunState {
+    tick_confusion(ecs);
+    if !hostile_in_view(ecs) {
+        let player_entity = ecs.fetch::<Entity>();
+        let mut combat_stats = ecs.write_storage::<CombatStats>();
+        if let Some(stats) = combat_stats.get_mut(*player_entity) {
+            let regen = i32::max(1, stats.max_hp / 20);
+            stats.hp = i32::min(stats.max_hp, stats.hp + regen);
         }
     }
     RunState::PlayerTurn
+}
+
+/// Spends a turn looking hard for hidden things nearby, with a much better chance than just noticing them.
+fn search(ecs: &mut World) -> RunState {
+    let player_pos = *ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let names = ecs.read_storage::<Name>();
+    let mut hidden = ecs.write_storage::<Hidden>();
+    let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+    let mut log = ecs.write_resource::<GameLog>();
+
+    let mut found = false;
+    if let Some(viewshed) = viewsheds.get(*player_entity) {
+        for tile in viewshed.visible_tiles.iter().filter(|tile| DistanceAlg::Pythagoras.distance2d(player_pos, **tile) <= SEARCH_RANGE) {
+            for e in map.tile_content[map.point2d_to_index(*tile)].iter() {
+                if hidden.get(*e).is_some() && rng.roll_dice(1, 3) > 1 {
+                    if let Some(name) = names.get(*e) {
+                        log.add(LogBuilder::system().text("You find a").colour(MAGENTA, &name.name).text("."));
+                    }
+                    hidden.remove(*e);
+                    found = true;
+                }
+            }
+        }
+    }
+    if !found {
+        log.add(LogBuilder::system().text("You search but find nothing."));
+    }
+    RunState::PlayerTurn
+}
+
+fn start_resting(ecs: &mut World) -> RunState {
+    let at_full_health = {
+        let player_entity = ecs.fetch::<Entity>();
+        let combat_stats = ecs.read_storage::<CombatStats>();
+        combat_stats.get(*player_entity).is_some_and(|stats| stats.hp >= stats.max_hp)
+    };
+    let refusal = if hostile_in_view(ecs) {
+        "You can't rest with enemies nearby."
+    } else if at_full_health {
+        "You are already at full health."
+    } else {
+        ecs.write_resource::<GameLog>().add(LogBuilder::system().text("You settle down to rest."));
+        return RunState::Resting;
+    };
+    ecs.write_resource::<GameLog>().add(LogBuilder::system().text(refusal));
+    RunState::AwaitingInput
+}
+
+pub fn player_input(ecs: &mut World, ctx: &mut BTerm) -> RunState {
+    if ctx.left_click {
+        return start_travel(ecs, ctx.mouse_point());
+    }
+
+    let Some(key) = ctx.key else { return RunState::AwaitingInput; };
+    let Some(command) = ecs.fetch::<KeyMap>().command(key) else { return RunState::AwaitingInput; };
+
+    if let Some((delta_x, delta_y)) = command.direction() {
+        try_move_player(delta_x, delta_y, ecs);
+        return RunState::PlayerTurn;
+    }
+
+    match command {
+        Command::Wait => skip_turn(ecs),
+        Command::Rest => start_resting(ecs),
+        Command::Search => search(ecs),
+        Command::PickUp => {
+            get_item(ecs);
+            RunState::PlayerTurn
+        }
+        Command::Inventory => RunState::ShowInventory,
+        Command::Fire => start_targeting(ecs),
+        Command::Descend => {
+            if try_next_level(ecs) { return RunState::NextLevel; }
+            RunState::AwaitingInput
+        }
+        Command::Look => RunState::Looking { cursor: *ecs.fetch::<Point>() },
+        Command::Explore => start_exploring(ecs),
+        Command::MessageLog => RunState::ShowLog { scroll: 0, filter: None },
+        Command::ToggleGraphics => {
+            let mut mode = ecs.write_resource::<GraphicsMode>();
+            *mode = mode.toggled();
+            RunState::AwaitingInput
+        }
+        Command::CycleFov => {
+            let algorithm = {
+                let mut algorithm = ecs.write_resource::<FovAlgorithm>();
+                *algorithm = algorithm.next();
+                *algorithm
+            };
+            for viewshed in (&mut ecs.write_storage::<Viewshed>()).join() {
+                viewshed.dirty = true;
+            }
+            ecs.write_resource::<GameLog>().add(LogBuilder::system().text(format!("Field of view: {}.", algorithm.describe())));
+            // Works out everyone's view again without using up a turn
+            RunState::PreRun
+        }
+        Command::Screenshot => {
+            ctx.screenshot("roguelike.png");
+            RunState::AwaitingInput
+        }
+        Command::Help => RunState::ShowHelp,
+        // Movement was handled above
+        _ => RunState::AwaitingInput
+    }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+use bracket_lib::prelude::*;
+
+pub struct RandomEntry {
+    name: String,
+    weight: i32,
+}
+
+/// A weighted table to roll spawns from. Entries with a weight below one never come up.
+#[derive(Default)]
+pub struct RandomTable {
+    entries: Vec<RandomEntry>,
+    total_weight: i32,
+}
+
+impl RandomTable {
+    pub fn new() -> Self {
+        RandomTable::default()
+    }
+
+    pub fn add<S: ToString>(mut self, name: S, weight: i32) -> Self {
+        if weight > 0 {
+            self.total_weight += weight;
+            self.entries.push(RandomEntry { name: name.to_string(), weight });
+        }
+        self
+    }
+
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> Option<String> {
+        if self.total_weight == 0 { return None; }
+        let mut roll = rng.roll_dice(1, self.total_weight) - 1;
+        for entry in self.entries.iter() {
+            if roll < entry.weight {
+                return Some(entry.name.clone());
+            }
+            roll -= entry.weight;
+        }
+        None
+    }
+}
@@ -0,0 +1,63 @@
+use specs::prelude::*;
+use bracket_lib::prelude::*;
+use super::prelude::*;
+
+const SHOT_NOISE: i32 = 4;
+
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (Entities<'a>,
+                       ReadExpect<'a, Entity>,
+                       ReadExpect<'a, Map>,
+                       WriteExpect<'a, GameLog>,
+                       WriteStorage<'a, WantsToShoot>,
+                       ReadStorage<'a, RangedWeapon>,
+                       ReadStorage<'a, Position>,
+                       ReadStorage<'a, Name>,
+                       ReadStorage<'a, CombatStats>,
+                       WriteStorage<'a, SufferDamage>,
+                       WriteStorage<'a, MakesNoise>,
+                       WriteExpect<'a, ParticleBuilder>
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player_entity, map, mut log, mut wants_shoot, weapons, positions, names, combat_stats, mut inflict_damage, mut noises, mut particles) = data;
+
+        for (entity, wants_shoot, weapon, pos, name, stats) in (&entities, &wants_shoot, &weapons, &positions, &names, &combat_stats).join() {
+            if stats.hp < 1 { continue; }
+            let (Some(target_pos), Some(target_stats)) = (positions.get(wants_shoot.target), combat_stats.get(wants_shoot.target)) else { continue; };
+            if target_stats.hp < 1 { continue; }
+
+            let start = Point::new(pos.x, pos.y);
+            let end = Point::new(target_pos.x, target_pos.y);
+            if DistanceAlg::Pythagoras.distance2d(start, end) > weapon.range as f32 || !map.has_line_of_sight(start, end) {
+                continue;
+            }
+
+            MakesNoise::new_noise(&mut noises, entity, SHOT_NOISE);
+            let target_name = names.get(wants_shoot.target).map_or("something", |n| n.name.as_str());
+            let damage = i32::max(0, weapon.damage - target_stats.defense);
+            if damage == 0 {
+                log.add(LogBuilder::combat()
+                    .actor(&name.name, entity == *player_entity)
+                    .text("shoots")
+                    .actor(target_name, wants_shoot.target == *player_entity)
+                    .text(", but it glances off."));
+            } else {
+                log.add(LogBuilder::combat()
+                    .actor(&name.name, entity == *player_entity)
+                    .text("shoots")
+                    .actor(target_name, wants_shoot.target == *player_entity)
+                    .text(",")
+                    .damage(damage)
+                    .text("."));
+                SufferDamage::new_damage(&mut inflict_damage, wants_shoot.target, damage);
+                particles.request(end.x, end.y, RGB::named(ORANGE), RGB::named(BLACK), to_cp437('‼'), 200.0);
+            }
+        }
+
+        wants_shoot.clear();
+    }
+}
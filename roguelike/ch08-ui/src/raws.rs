@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use bracket_lib::prelude::*;
+use serde::Deserialize;
+use specs::prelude::*;
+use super::prelude::*;
+
+const SPAWNS_JSON: &str = include_str!("../raws/spawns.json");
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawStats {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawRanged {
+    pub range: i32,
+    pub damage: i32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawLight {
+    pub color: String,
+    pub range: i32,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RawAi { Monster, Companion }
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawMonster {
+    pub name: String,
+    pub description: Option<String>,
+    pub glyph: String,
+    pub sprite: Option<FontCharType>,
+    pub fg: String,
+    pub bg: String,
+    pub stats: RawStats,
+    pub vision_range: i32,
+    pub hearing_range: Option<i32>,
+    pub ai: RawAi,
+    pub leash: Option<i32>,
+    pub faction: String,
+    pub ranged: Option<RawRanged>,
+    pub light: Option<RawLight>,
+}
+
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct RawEffects {
+    pub provides_healing: Option<i32>,
+    #[serde(default)]
+    pub provides_food: bool,
+    pub inflicts_damage: Option<i32>,
+    #[serde(default)]
+    pub teleports: bool,
+    pub alarm: Option<i32>,
+    pub confusion: Option<i32>,
+    #[serde(default)]
+    pub magic_mapping: bool,
+    pub telepathy: Option<i32>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawItem {
+    pub name: String,
+    pub description: Option<String>,
+    pub glyph: String,
+    pub sprite: Option<FontCharType>,
+    pub fg: String,
+    pub bg: String,
+    #[serde(default)]
+    pub consumable: bool,
+    #[serde(default)]
+    pub effects: RawEffects,
+    pub light: Option<RawLight>,
+}
+
+fn default_hidden() -> bool { true }
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawTrap {
+    pub name: String,
+    pub description: Option<String>,
+    pub glyph: String,
+    pub sprite: Option<FontCharType>,
+    pub fg: String,
+    pub bg: String,
+    #[serde(default = "default_hidden")]
+    pub hidden: bool,
+    pub effects: RawEffects,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawFaction {
+    pub name: String,
+    pub responses: HashMap<String, Reaction>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RawSpawnEntry {
+    pub name: String,
+    pub weight: i32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+    pub cost: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Raws {
+    pub monsters: Vec<RawMonster>,
+    pub items: Vec<RawItem>,
+    pub traps: Vec<RawTrap>,
+    pub spawn_table: Vec<RawSpawnEntry>,
+    pub factions: Vec<RawFaction>,
+}
+
+/// The game's content, loaded from `raws/spawns.json` and looked up by name.
+pub struct RawMaster {
+    raws: Raws,
+    monster_index: HashMap<String, usize>,
+    item_index: HashMap<String, usize>,
+    trap_index: HashMap<String, usize>,
+}
+
+impl RawMaster {
+    pub fn load() -> Self {
+        RawMaster::from_json(SPAWNS_JSON).expect("Unable to parse raws/spawns.json")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let raws: Raws = serde_json::from_str(json)?;
+        let monster_index = raws.monsters.iter().enumerate().map(|(i, m)| (m.name.clone(), i)).collect();
+        let item_index = raws.items.iter().enumerate().map(|(i, item)| (item.name.clone(), i)).collect();
+        let trap_index = raws.traps.iter().enumerate().map(|(i, trap)| (trap.name.clone(), i)).collect();
+        Ok(RawMaster { raws, monster_index, item_index, trap_index })
+    }
+
+    pub fn monster(&self, name: &str) -> Option<&RawMonster> {
+        self.monster_index.get(name).map(|i| &self.raws.monsters[*i])
+    }
+
+    pub fn item(&self, name: &str) -> Option<&RawItem> {
+        self.item_index.get(name).map(|i| &self.raws.items[*i])
+    }
+
+    pub fn trap(&self, name: &str) -> Option<&RawTrap> {
+        self.trap_index.get(name).map(|i| &self.raws.traps[*i])
+    }
+
+    /// Whether `name` is a monster, item or trap that can be spawned.
+    pub fn knows(&self, name: &str) -> bool {
+        self.monster(name).is_some() || self.item(name).is_some() || self.trap(name).is_some()
+    }
+
+    /// Everything that may spawn at `depth`, weighted by how often it should.
+    pub fn spawn_table(&self, depth: i32) -> RandomTable {
+        self.raws.spawn_table.iter()
+            .filter(|entry| depth >= entry.min_depth && depth <= entry.max_depth)
+            .fold(RandomTable::new(), |table, entry| table.add(&entry.name, entry.weight))
+    }
+
+    /// How much of a level's spawn budget `name` uses up. Defaults to one.
+    pub fn spawn_cost(&self, name: &str) -> i32 {
+        self.raws.spawn_table.iter()
+            .find(|entry| entry.name == name)
+            .and_then(|entry| entry.cost)
+            .unwrap_or(1)
+    }
+
+    pub fn faction_table(&self) -> FactionTable {
+        let mut table = FactionTable::default();
+        for faction in self.raws.factions.iter() {
+            for (other, reaction) in faction.responses.iter() {
+                table.set(&faction.name, other, *reaction);
+            }
+        }
+        table
+    }
+}
+
+fn renderable(glyph: &str, sprite: Option<FontCharType>, fg: &str, bg: &str, render_order: i32) -> Renderable {
+    Renderable {
+        glyph: to_cp437(glyph.chars().next().expect("Empty glyph in raws")),
+        sprite,
+        fg: RGB::from_hex(fg).expect("Invalid foreground colour in raws"),
+        bg: RGB::from_hex(bg).expect("Invalid background colour in raws"),
+        render_order,
+    }
+}
+
+fn light_source(raw: &RawLight) -> LightSource {
+    LightSource {
+        color: RGB::from_hex(&raw.color).expect("Invalid light colour in raws"),
+        range: raw.range,
+    }
+}
+
+fn spawn_monster(ecs: &mut World, raw: RawMonster, pos: Point) -> Entity {
+    let mut monster = ecs.create_entity()
+        .with(Position { x: pos.x, y: pos.y })
+        .with(renderable(&raw.glyph, raw.sprite, &raw.fg, &raw.bg, 1))
+        .with(Viewshed::new(raw.vision_range))
+        .with(Monster {})
+        .with(AiState::Idle)
+        .with(TargetMemory::default())
+        .with(Faction { name: raw.faction })
+        .with(Name { name: raw.name })
+        .with(BlocksTile{})
+        .with(CombatStats{ max_hp: raw.stats.max_hp, hp: raw.stats.max_hp, defense: raw.stats.defense, power: raw.stats.power });
+    if raw.ai == RawAi::Companion {
+        monster = monster.with(Companion { leash: raw.leash.unwrap_or(3) });
+    }
+    if let Some(range) = raw.hearing_range {
+        monster = monster.with(Hearing { range });
+    }
+    if let Some(ranged) = raw.ranged {
+        monster = monster.with(RangedWeapon { range: ranged.range, damage: ranged.damage });
+    }
+    if let Some(light) = raw.light {
+        monster = monster.with(light_source(&light));
+    }
+    if let Some(text) = raw.description {
+        monster = monster.with(Description { text });
+    }
+    monster.build()
+}
+
+/// Attaches a component for every effect listed in the raws.
+fn with_effects<'a>(mut builder: EntityBuilder<'a>, effects: &RawEffects) -> EntityBuilder<'a> {
+    if let Some(heal_amount) = effects.provides_healing {
+        builder = builder.with(ProvidesHealing { heal_amount });
+    }
+    if effects.provides_food {
+        builder = builder.with(ProvidesFood {});
+    }
+    if let Some(damage) = effects.inflicts_damage {
+        builder = builder.with(InflictsDamage { damage });
+    }
+    if effects.teleports {
+        builder = builder.with(TeleportsVictim {});
+    }
+    if let Some(volume) = effects.alarm {
+        builder = builder.with(RaisesAlarm { volume });
+    }
+    if let Some(turns) = effects.confusion {
+        builder = builder.with(CausesConfusion { turns });
+    }
+    if effects.magic_mapping {
+        builder = builder.with(MagicMapper {});
+    }
+    if let Some(turns) = effects.telepathy {
+        builder = builder.with(ProvidesTelepathy { turns });
+    }
+    builder
+}
+
+fn spawn_item(ecs: &mut World, raw: RawItem, pos: Point) -> Entity {
+    let mut item = ecs.create_entity()
+        .with(Position { x: pos.x, y: pos.y })
+        .with(renderable(&raw.glyph, raw.sprite, &raw.fg, &raw.bg, 2))
+        .with(Item {})
+        .with(Name { name: raw.name });
+    if raw.consumable {
+        item = item.with(Consumable {});
+    }
+    if let Some(light) = &raw.light {
+        item = item.with(light_source(light));
+    }
+    if let Some(text) = raw.description {
+        item = item.with(Description { text });
+    }
+    with_effects(item, &raw.effects).build()
+}
+
+fn spawn_trap(ecs: &mut World, raw: RawTrap, pos: Point) -> Entity {
+    let mut trap = ecs.create_entity()
+        .with(Position { x: pos.x, y: pos.y })
+        .with(renderable(&raw.glyph, raw.sprite, &raw.fg, &raw.bg, 3))
+        .with(EntryTrigger {})
+        .with(Name { name: raw.name });
+    if raw.hidden {
+        trap = trap.with(Hidden {});
+    }
+    if let Some(text) = raw.description {
+        trap = trap.with(Description { text });
+    }
+    with_effects(trap, &raw.effects).build()
+}
+
+/// Builds the monster, item or trap called `name` at `pos`. Returns None if the raws don't know it.
+pub fn spawn_named_entity(ecs: &mut World, name: &str, pos: Point) -> Option<Entity> {
+    let (monster, item, trap) = {
+        let raws = ecs.fetch::<RawMaster>();
+        (raws.monster(name).cloned(), raws.item(name).cloned(), raws.trap(name).cloned())
+    };
+
+    if let Some(monster) = monster {
+        Some(spawn_monster(ecs, monster, pos))
+    } else if let Some(item) = item {
+        Some(spawn_item(ecs, item, pos))
+    } else {
+        trap.map(|trap| spawn_trap(ecs, trap, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_table_names_are_defined() {
+        let raws = RawMaster::load();
+        for entry in raws.raws.spawn_table.iter() {
+            assert!(raws.knows(&entry.name), "spawn_table entry \"{}\" is not a monster, item or trap", entry.name);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+embedded_resource!(SPRITE_SHEET, "../resources/sprite.png");
+
+const ASCII_FONT: &str = "terminal8x8.png";
+const SPRITE_FONT: &str = "sprite.png";
+const CONSOLES: usize = 8;
+
+/// Plain `ctx` calls draw here: the status panel, menus and tooltips, on top of everything else.
+pub const UI_CONSOLE: usize = 7;
+
+/// Whether things with a sprite are drawn with it, or as their CP437 glyph.
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum GraphicsMode {
+    #[default]
+    Ascii,
+    Tiles,
+}
+
+impl GraphicsMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            GraphicsMode::Ascii => GraphicsMode::Tiles,
+            GraphicsMode::Tiles => GraphicsMode::Ascii,
+        }
+    }
+
+    /// The sprite to draw in this mode, if any. Without one, the glyph is drawn instead.
+    pub fn sprite(self, sprite: Option<FontCharType>) -> Option<FontCharType> {
+        match self {
+            GraphicsMode::Ascii => None,
+            GraphicsMode::Tiles => sprite,
+        }
+    }
+}
+
+/// Layers are drawn bottom to top. Each has a glyph console with the sprite console above it.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RenderLayer { Map, Items, Actors, Effects, Ui }
+
+impl RenderLayer {
+    pub fn console(self, sprite: bool) -> usize {
+        match (self, sprite) {
+            (RenderLayer::Map, false) => 0,
+            (RenderLayer::Map, true) => 1,
+            (RenderLayer::Items, false) => 2,
+            (RenderLayer::Items, true) => 3,
+            (RenderLayer::Actors, false) => 4,
+            (RenderLayer::Actors, true) => 5,
+            // Particles and the UI don't have sprites
+            (RenderLayer::Effects, _) => 6,
+            (RenderLayer::Ui, _) => UI_CONSOLE,
+        }
+    }
+
+    /// Anything that can fight is an actor; everything else lying around is an item.
+    pub fn of(entity: Entity, combat_stats: &ReadStorage<CombatStats>) -> Self {
+        if combat_stats.get(entity).is_some() { RenderLayer::Actors } else { RenderLayer::Items }
+    }
+}
+
+/// A terminal with a glyph and a sprite console for each layer, using the `ch05-fov` sprite sheet.
+/// Cells stay 8x8 as with `simple80x50`, so the window (and the wasm canvas) keeps its size;
+/// the 16x16 sprites are scaled down to fit.
+pub fn terminal(width: i32, height: i32) -> BTermBuilder {
+    link_resource!(SPRITE_SHEET, "resources/sprite.png");
+    BTermBuilder::new()
+        .with_dimensions(width, height)
+        .with_tile_dimensions(8u32, 8u32)
+        .with_font(ASCII_FONT, 8u32, 8u32)
+        .with_font(SPRITE_FONT, 16u32, 16u32)
+        .with_simple_console(width, height, ASCII_FONT)
+        .with_sparse_console_no_bg(width, height, SPRITE_FONT)
+        .with_sparse_console(width, height, ASCII_FONT)
+        .with_sparse_console_no_bg(width, height, SPRITE_FONT)
+        .with_sparse_console(width, height, ASCII_FONT)
+        .with_sparse_console_no_bg(width, height, SPRITE_FONT)
+        .with_sparse_console(width, height, ASCII_FONT)
+        .with_sparse_console(width, height, ASCII_FONT)
+}
+
+/// Clears every layer, leaving the UI console active.
+pub fn clear_layers(ctx: &mut BTerm) {
+    for console in 0..CONSOLES {
+        ctx.set_active_console(console);
+        ctx.cls();
+    }
+}
+
+/// Draws a batch, then hands the active console back to the UI.
+pub fn submit(batch: &mut DrawBatch, ctx: &mut BTerm) {
+    batch.submit(0).expect("Batch error");
+    render_draw_buffer(ctx).expect("Render error");
+    ctx.set_active_console(UI_CONSOLE);
+}
+
+/// Sprite sheets bring their own colours, so sprites are only shaded, over nothing.
+pub fn sprite_colours(shade: RGB) -> ColorPair {
+    ColorPair::new(shade, RGBA::from_f32(0.0, 0.0, 0.0, 0.0))
+}
+
+pub fn draw_entities(ecs: &World, ctx: &mut BTerm) {
+    let mut batch = DrawBatch::new();
+    let mode = *ecs.fetch::<GraphicsMode>();
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let particles = ecs.read_storage::<ParticleLifetime>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let monsters = ecs.read_storage::<Monster>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let entities = ecs.entities();
+    let player_entity = *ecs.fetch::<Entity>();
+    let telepathic = ecs.read_storage::<Telepathy>().get(player_entity).is_some();
+
+    // Where things were last seen, dimmed, unless we can sense where they are right now
+    let mut memories: Vec<&RememberedEntity> = map.remembered.iter()
+        .filter(|(entity, _)| !(telepathic && monsters.get(**entity).is_some()))
+        .map(|(_, memory)| memory)
+        .filter(|memory| {
+            let idx = map.point2d_to_index(memory.point);
+            !map.visible_tiles[idx] && map.revealed_tiles[idx]
+        })
+        .collect();
+    // Whatever is drawn last ends up on top
+    memories.sort_by_key(|memory| std::cmp::Reverse(memory.render_order));
+    for memory in memories {
+        match mode.sprite(memory.sprite) {
+            Some(sprite) => batch.target(memory.layer.console(true)).set(memory.point, sprite_colours(RGB::named(WHITE) * 0.4), sprite),
+            None => batch.target(memory.layer.console(false)).set(memory.point, ColorPair::new(memory.fg * 0.4, RGB::named(BLACK)), memory.glyph),
+        };
+    }
+
+    let mut data: Vec<(Entity, Point, &Renderable)> = (&entities, &positions, &renderables, !&hidden).join()
+        .map(|(entity, pos, render, _)| (entity, Point::new(pos.x, pos.y), render))
+        .filter(|(entity, point, _)| map.visible_tiles[map.point2d_to_index(*point)] || (telepathic && monsters.get(*entity).is_some()))
+        .collect();
+    data.sort_by_key(|(_, _, render)| std::cmp::Reverse(render.render_order));
+    for (entity, point, render) in data {
+        let idx = map.point2d_to_index(point);
+        let is_particle = particles.get(entity).is_some();
+        let layer = if is_particle { RenderLayer::Effects } else { RenderLayer::of(entity, &combat_stats) };
+        // Lit like the tile they stand on. Particles glow, and what telepathy senses isn't seen at all
+        let shade = if is_particle || !map.visible_tiles[idx] { RGB::named(WHITE) } else { map.light_at(idx) };
+        match mode.sprite(render.sprite) {
+            Some(sprite) => batch.target(layer.console(true)).set(point, sprite_colours(shade), sprite),
+            None => batch.target(layer.console(false)).set(point, ColorPair::new(render.fg * shade, render.bg), render.glyph),
+        };
+    }
+
+    submit(&mut batch, ctx);
+}
@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+/// The wizard from the `ch05-fov` sprite sheet.
+const PLAYER_SPRITE: FontCharType = 10;
+
+pub fn player(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable { glyph: to_cp437('@'), sprite: Some(PLAYER_SPRITE), fg: RGB::named(YELLOW), bg: RGB::named(BLACK), render_order: 0 })
+        .with(Viewshed::new(8))
+        .with(Player {})
+        .with(Name { name: "Rust".to_string() })
+        .with(Description { text: "That's you: a rusty adventurer, a long way from the surface.".to_string() })
+        .with(CombatStats{ max_hp: 30, hp: 30, defense: 2, power: 5 })
+        .with(RangedWeapon{ range: 6, damage: 4 })
+        .with(Faction { name: "Player".to_string() })
+        .with(HungerClock::well_fed())
+        .with(LightSource { color: RGB::from_f32(1.0, 1.0, 0.8), range: 8 })
+        .build()
+}
+
+pub fn companion(ecs: &mut World, x: i32, y: i32) -> Entity {
+    spawn_named_entity(ecs, "Dog", Point::new(x, y)).expect("No Dog in the raws")
+}
+
+const MAX_SPAWNS_PER_ROOM: i32 = 4;
+const BASE_BUDGET: i32 = 12;
+const BUDGET_PER_DEPTH: i32 = 4;
+
+/// Picks a floor tile in `room` that nothing has been spawned on yet.
+fn free_room_tile(rng: &mut RandomNumberGenerator, map: &Map, room: &Rect, taken: &HashSet<Point>) -> Option<Point> {
+    for _ in 0..20 {
+        let point = Point::new(rng.range(room.x1 + 1, room.x2 + 1), rng.range(room.y1 + 1, room.y2 + 1));
+        if map.tiles[map.point2d_to_index(point)] == TileType::Floor && !taken.contains(&point) {
+            return Some(point);
+        }
+    }
+    None
+}
+
+/// Fills every room but the first, which is where the player starts, from the depth's spawn table
+/// until the level's budget runs out. Deeper levels get a bigger budget and nastier tables.
+pub fn populate_rooms(ecs: &mut World) {
+    let (mut rooms, depth) = {
+        let map = ecs.fetch::<Map>();
+        (map.rooms.iter().skip(1).copied().collect::<Vec<Rect>>(), map.depth)
+    };
+    let mut budget = BASE_BUDGET + depth * BUDGET_PER_DEPTH;
+
+    let mut spawns: Vec<(String, Point)> = Vec::new();
+    {
+        let raws = ecs.fetch::<RawMaster>();
+        let map = ecs.fetch::<Map>();
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let table = raws.spawn_table(depth);
+        let mut taken: HashSet<Point> = HashSet::new();
+
+        // Shuffle the rooms so the budget doesn't always run dry in the same corner of the map
+        for i in (1..rooms.len()).rev() {
+            let j = rng.range(0, i + 1);
+            rooms.swap(i, j);
+        }
+
+        for room in rooms.iter() {
+            let count = rng.roll_dice(1, MAX_SPAWNS_PER_ROOM + 2) - 2;
+            for _ in 0..count {
+                let Some(name) = table.roll(&mut rng) else { break; };
+                // A name the raws don't define would spawn nothing, so it shouldn't use up the budget
+                if !raws.knows(&name) { continue; }
+                let cost = raws.spawn_cost(&name);
+                if cost > budget { continue; }
+                if let Some(spot) = free_room_tile(&mut rng, &map, room, &taken) {
+                    budget -= cost;
+                    taken.insert(spot);
+                    spawns.push((name, spot));
+                }
+            }
+        }
+    }
+
+    for (name, spot) in spawns.iter() {
+        spawn_named_entity(ecs, name, *spot);
+    }
+}
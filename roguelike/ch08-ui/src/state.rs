@@ -1,9 +1,10 @@
 use bracket_lib::prelude::*;
 use specs::prelude::*;
 use super::prelude::*;
+use super::spawner;
 
 #[derive(PartialEq, Copy, Clone)]
-pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn }
+pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn, ShowTargeting { range: i32 }, ShowInventory, NextLevel, Resting, MagicMapReveal { row: i32 }, ShowLog { scroll: i32, filter: Option<LogCategory> }, Looking { cursor: Point }, Travelling { destination: Point }, Exploring, ShowHelp }
 
 pub struct State {
     pub ecs: World,
@@ -11,7 +12,11 @@ pub struct State {
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut BTerm) {
-        ctx.cls();
+        clear_layers(ctx);
+        cull_dead_particles(&mut self.ecs, ctx);
+        draw_map(&self.ecs, ctx);
+        draw_entities(&self.ecs, ctx);
+        draw_ui(&self.ecs, ctx);
 
         let mut newrunstate;
         {
@@ -29,12 +34,79 @@ impl GameState for State {
             }
             RunState::PlayerTurn => {
                 self.run_systems();
-                newrunstate = RunState::MonsterTurn;
+                // Using an item can start an effect that needs to play out first
+                newrunstate = match *self.ecs.fetch::<RunState>() {
+                    RunState::MagicMapReveal { .. } => RunState::MagicMapReveal { row: 0 },
+                    _ => RunState::MonsterTurn
+                };
+            }
+            RunState::MagicMapReveal { row } => {
+                let mut map = self.ecs.write_resource::<Map>();
+                for x in 0..map.width {
+                    let idx = map.point2d_to_index(Point::new(x, row));
+                    map.revealed_tiles[idx] = true;
+                }
+                newrunstate = if row + 1 >= map.height { RunState::MonsterTurn } else { RunState::MagicMapReveal { row: row + 1 } };
             }
             RunState::MonsterTurn => {
                 self.run_systems();
                 newrunstate = RunState::AwaitingInput;
             }
+            RunState::Resting => {
+                newrunstate = self.rest_one_turn();
+            }
+            // Any key or click takes back control
+            RunState::Travelling { .. } | RunState::Exploring if ctx.key.is_some() || ctx.left_click => {
+                newrunstate = RunState::AwaitingInput;
+            }
+            RunState::Travelling { destination } => {
+                newrunstate = self.travel_one_turn(Some(destination));
+            }
+            RunState::Exploring => {
+                newrunstate = self.travel_one_turn(None);
+            }
+            RunState::ShowInventory => {
+                match show_inventory(&self.ecs, ctx) {
+                    (ItemMenuResult::Cancel, _) => newrunstate = RunState::AwaitingInput,
+                    (ItemMenuResult::Selected, Some(item)) => {
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                        intent.insert(player_entity, WantsToUseItem{ item }).expect("Unable to insert intent");
+                        newrunstate = RunState::PlayerTurn;
+                    }
+                    _ => {}
+                }
+            }
+            RunState::ShowLog { scroll, filter } => {
+                newrunstate = match show_log(&self.ecs, ctx, scroll, filter) {
+                    LogViewResult::Close => RunState::AwaitingInput,
+                    LogViewResult::Browse { scroll, filter } => RunState::ShowLog { scroll, filter },
+                };
+            }
+            RunState::Looking { cursor } => {
+                newrunstate = match look(&self.ecs, ctx, cursor) {
+                    LookResult::Close => RunState::AwaitingInput,
+                    LookResult::Looking { cursor } => RunState::Looking { cursor },
+                };
+            }
+            RunState::ShowHelp => {
+                if show_help(&self.ecs, ctx) == HelpResult::Close {
+                    newrunstate = RunState::AwaitingInput;
+                }
+            }
+            RunState::NextLevel => {
+                self.goto_next_level();
+                newrunstate = RunState::PreRun;
+            }
+            RunState::ShowTargeting { range } => {
+                match ranged_target(&self.ecs, ctx, range) {
+                    (TargetingResult::Cancel, _) => newrunstate = RunState::AwaitingInput,
+                    (TargetingResult::Selected, Some(target)) if fire_at(&mut self.ecs, target) => {
+                        newrunstate = RunState::PlayerTurn;
+                    }
+                    _ => {}
+                }
+            }
         }
 
         {
@@ -43,40 +115,207 @@ impl GameState for State {
         }
 
         delete_the_dead(&mut self.ecs);
-        draw_map(&self.ecs, ctx);
 
-        let map = self.ecs.fetch::<Map>();
+        // Particles are spawned every frame, not every turn, so effects never wait on input
+        let mut particles = ParticleSpawnSystem{};
+        particles.run_now(&self.ecs);
+    }
+}
+
+impl State {
+    /// Everything except the player, their companions and what they carry is left behind on a level change.
+    fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
+        let entities = self.ecs.entities();
+        let players = self.ecs.read_storage::<Player>();
+        let companions = self.ecs.read_storage::<Companion>();
+        let backpack = self.ecs.read_storage::<InBackpack>();
+
+        let travels = |entity: Entity| players.get(entity).is_some() || companions.get(entity).is_some();
+        (&entities).join()
+            .filter(|entity| !travels(*entity) && !backpack.get(*entity).is_some_and(|item| travels(item.owner)))
+            .collect()
+    }
+
+    fn goto_next_level(&mut self) {
+        for target in self.entities_to_remove_on_level_change() {
+            self.ecs.delete_entity(target).expect("Unable to delete entity");
+        }
+
+        let (width, height, depth) = {
+            let map = self.ecs.fetch::<Map>();
+            (map.width, map.height, map.depth + 1)
+        };
+        let map = Map::new_map_rooms_and_corridors(width, height, depth);
+        let start = map.rooms[0].center();
+        *self.ecs.write_resource::<Map>() = map;
+        spawner::populate_rooms(&mut self.ecs);
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        for (pos, render) in (&positions, &renderables).join() {
-            let idx = map.point2d_to_index(Point::new(pos.x, pos.y));
-            if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
+        {
+            let player_entity = *self.ecs.fetch::<Entity>();
+            let map = self.ecs.fetch::<Map>();
+            let mut player_pos = self.ecs.write_resource::<Point>();
+            let mut positions = self.ecs.write_storage::<Position>();
+            let mut viewsheds = self.ecs.write_storage::<Viewshed>();
+            let mut ai_states = self.ecs.write_storage::<AiState>();
+            let mut memories = self.ecs.write_storage::<TargetMemory>();
+            let companions = self.ecs.read_storage::<Companion>();
+
+            *player_pos = start;
+            if let Some(pos) = positions.get_mut(player_entity) {
+                (pos.x, pos.y) = (start.x, start.y);
+            }
+            if let Some(viewshed) = viewsheds.get_mut(player_entity) {
+                viewshed.dirty = true;
+            }
+
+            // Companions arrive next to the player, with nothing left to chase from the last level
+            let mut spots: Vec<Point> = map.rooms[0].point_set().into_iter()
+                .filter(|spot| *spot != start && map.tiles[map.point2d_to_index(*spot)] == TileType::Floor)
+                .collect();
+            spots.sort_by(|a, b| {
+                let da = DistanceAlg::Pythagoras.distance2d(start, *a);
+                let db = DistanceAlg::Pythagoras.distance2d(start, *b);
+                da.total_cmp(&db)
+            });
+            let mut spots = spots.into_iter();
+            for (pos, viewshed, state, memory, _companion) in (&mut positions, &mut viewsheds, &mut ai_states, &mut memories, &companions).join() {
+                let spot = spots.next().unwrap_or(start);
+                (pos.x, pos.y) = (spot.x, spot.y);
+                viewshed.dirty = true;
+                *state = AiState::Idle;
+                *memory = TargetMemory::default();
             }
         }
 
-        draw_ui(&self.ecs, ctx);
+        self.ecs.write_resource::<GameLog>().add(LogBuilder::system().text("You descend to level").colour(CYAN, depth).text("."));
+    }
+
+    fn player_hp(&self) -> i32 {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        self.ecs.read_storage::<CombatStats>().get(player_entity).map_or(0, |stats| stats.hp)
+    }
+
+    /// Runs the player's turn and the monsters' reply straight away, without waiting for input.
+    fn run_whole_turn(&mut self) {
+        *self.ecs.write_resource::<RunState>() = RunState::PlayerTurn;
+        self.run_systems();
+        *self.ecs.write_resource::<RunState>() = RunState::MonsterTurn;
+        self.run_systems();
+    }
+
+    /// Plays out a whole waiting turn, monsters included, then decides whether to keep resting.
+    fn rest_one_turn(&mut self) -> RunState {
+        skip_turn(&mut self.ecs);
+        let healed_hp = self.player_hp();
+        self.run_whole_turn();
+
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let fully_healed = self.ecs.read_storage::<CombatStats>().get(player_entity).is_none_or(|stats| stats.hp >= stats.max_hp);
+        let interruption = if self.player_hp() < healed_hp {
+            Some("You are hurt and stop resting.")
+        } else if hostile_in_view(&self.ecs) {
+            Some("An enemy comes into view and you stop resting.")
+        } else if fully_healed {
+            Some("You feel fully rested.")
+        } else {
+            None
+        };
+
+        match interruption {
+            Some(message) => {
+                self.ecs.write_resource::<GameLog>().add(LogBuilder::system().text(message));
+                RunState::AwaitingInput
+            }
+            None => RunState::Resting
+        }
+    }
+
+    /// Plays out a whole turn of travelling towards `destination`, or of exploring if it's None,
+    /// then decides whether to keep going.
+    fn travel_one_turn(&mut self, destination: Option<Point>) -> RunState {
+        let hp = self.player_hp();
+        let start = *self.ecs.fetch::<Point>();
+        if !travel_step(&mut self.ecs, destination) {
+            let message = if destination.is_some() { "You can't find a way there." } else { "There is nowhere left to explore." };
+            self.ecs.write_resource::<GameLog>().add(LogBuilder::system().text(message));
+            return RunState::AwaitingInput;
+        }
+        self.run_whole_turn();
+
+        let position = *self.ecs.fetch::<Point>();
+        let interruption = if self.player_hp() < hp {
+            Some("You are hurt and stop.")
+        } else if hostile_in_view(&self.ecs) {
+            Some("An enemy comes into view and you stop.")
+        } else {
+            None
+        };
+
+        if let Some(message) = interruption {
+            self.ecs.write_resource::<GameLog>().add(LogBuilder::system().text(message));
+            return RunState::AwaitingInput;
+        }
+        // Something got in the way, or we've arrived
+        if position == start || Some(position) == destination {
+            return RunState::AwaitingInput;
+        }
+        match destination {
+            Some(destination) => RunState::Travelling { destination },
+            None => RunState::Exploring,
+        }
     }
-}
 
-impl State {
     fn run_systems(&mut self) {
+        if *self.ecs.fetch::<RunState>() == RunState::PlayerTurn {
+            self.ecs.write_resource::<GameLog>().next_turn();
+        }
+
         let mut vis = VisibilitySystem {};
         vis.run_now(&self.ecs);
 
+        let mut dijkstra = DijkstraMapSystem {};
+        dijkstra.run_now(&self.ecs);
+
         let mut mob = MonsterAI {};
         mob.run_now(&self.ecs);
 
         let mut mapindex = MapIndexingSystem {};
         mapindex.run_now(&self.ecs);
 
+        let mut triggers = TriggerSystem{};
+        triggers.run_now(&self.ecs);
+
         let mut melee = MeleeCombatSystem{};
         melee.run_now(&self.ecs);
 
+        let mut ranged = RangedCombatSystem{};
+        ranged.run_now(&self.ecs);
+
+        let mut hunger = HungerSystem{};
+        hunger.run_now(&self.ecs);
+
+        let mut telepathy = TelepathySystem{};
+        telepathy.run_now(&self.ecs);
+
         let mut damage = DamageSystem{};
         damage.run_now(&self.ecs);
 
+        let mut pickup = ItemCollectionSystem{};
+        pickup.run_now(&self.ecs);
+
+        let mut items = ItemUseSystem{};
+        items.run_now(&self.ecs);
+
+        let mut noise = NoiseSystem{};
+        noise.run_now(&self.ecs);
+
+        // Last, so the light follows everything that moved this turn
+        let mut lighting = LightingSystem{};
+        lighting.run_now(&self.ecs);
+
+        let mut memory = EntityMemorySystem{};
+        memory.run_now(&self.ecs);
+
         self.ecs.maintain();
     }
 }
\ No newline at end of file
@@ -0,0 +1,34 @@
+use specs::prelude::*;
+use super::prelude::*;
+
+/// Counts telepathy down once per player turn and takes it away when it runs out.
+pub struct TelepathySystem {}
+
+impl<'a> System<'a> for TelepathySystem {
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, Telepathy> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player_entity, runstate, mut log, mut telepathy) = data;
+
+        if *runstate != RunState::PlayerTurn { return; }
+
+        let mut expired: Vec<Entity> = Vec::new();
+        for (entity, telepathy) in (&entities, &mut telepathy).join() {
+            telepathy.turns -= 1;
+            if telepathy.turns < 1 {
+                expired.push(entity);
+            }
+        }
+
+        for entity in expired {
+            telepathy.remove(entity);
+            if entity == *player_entity {
+                log.add(LogBuilder::system().text("Your mind's eye closes."));
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+const MAX_EXPLORE_DEPTH: f32 = 1000.0;
+
+/// The map as the player knows it. Travel only crosses tiles they have seen and only steps around
+/// the traps they have found; `Map`'s own exits make every trap costly, which would give hidden ones away.
+struct KnownMap<'a> {
+    map: &'a Map,
+    blocked: Vec<bool>,
+}
+
+impl<'a> KnownMap<'a> {
+    fn new(ecs: &World, map: &'a Map) -> Self {
+        let positions = ecs.read_storage::<Position>();
+        let triggers = ecs.read_storage::<EntryTrigger>();
+        let hidden = ecs.read_storage::<Hidden>();
+
+        let mut blocked: Vec<bool> = map.tiles.iter().zip(map.revealed_tiles.iter())
+            .map(|(tile, revealed)| *tile == TileType::Wall || !revealed)
+            .collect();
+        for (pos, _trigger, _) in (&positions, &triggers, !&hidden).join() {
+            blocked[map.point2d_to_index(Point::new(pos.x, pos.y))] = true;
+        }
+        KnownMap { map, blocked }
+    }
+
+    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
+        if x < 1 || x > self.map.width - 1 || y < 1 || y > self.map.height - 1 { return false; }
+        !self.blocked[self.map.point2d_to_index(Point::new(x, y))]
+    }
+
+    /// Walkable tiles next to somewhere the player hasn't seen yet.
+    fn frontier(&self) -> Vec<usize> {
+        (0..self.blocked.len())
+            .filter(|idx| !self.blocked[*idx])
+            .filter(|idx| {
+                let point = self.map.index_to_point2d(*idx);
+                (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| Point::new(point.x + dx, point.y + dy)))
+                    .filter(|n| n.x >= 0 && n.x < self.map.width && n.y >= 0 && n.y < self.map.height)
+                    .any(|n| !self.map.revealed_tiles[self.map.point2d_to_index(n)])
+            })
+            .collect()
+    }
+}
+
+impl BaseMap for KnownMap<'_> {
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        let mut exits = SmallVec::new();
+        let point = self.map.index_to_point2d(idx);
+        let (x, y) = (point.x, point.y);
+        let w = self.map.width as usize;
+
+        // Cardinal directions
+        if self.is_exit_valid(x - 1, y) { exits.push((idx - 1, 1.0)) };
+        if self.is_exit_valid(x + 1, y) { exits.push((idx + 1, 1.0)) };
+        if self.is_exit_valid(x, y - 1) { exits.push((idx - w, 1.0)) };
+        if self.is_exit_valid(x, y + 1) { exits.push((idx + w, 1.0)) };
+
+        // Diagonals
+        if self.is_exit_valid(x - 1, y - 1) { exits.push(((idx - w) - 1, 1.45)); }
+        if self.is_exit_valid(x + 1, y - 1) { exits.push(((idx - w) + 1, 1.45)); }
+        if self.is_exit_valid(x - 1, y + 1) { exits.push(((idx + w) - 1, 1.45)); }
+        if self.is_exit_valid(x + 1, y + 1) { exits.push(((idx + w) + 1, 1.45)); }
+
+        exits
+    }
+}
+
+/// The next tile on the way to `destination`, or towards the nearest unexplored spot if there is none.
+fn next_step(ecs: &World, destination: Option<Point>) -> Option<Point> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = *ecs.fetch::<Point>();
+    let known = KnownMap::new(ecs, &map);
+    let start = map.point2d_to_index(player_pos);
+
+    match destination {
+        Some(destination) => {
+            if destination == player_pos { return None; }
+            let path = a_star_search(start, map.point2d_to_index(destination), &known);
+            if path.success && path.steps.len() > 1 { Some(map.index_to_point2d(path.steps[1])) } else { None }
+        }
+        None => {
+            let dm = DijkstraMap::new(map.width, map.height, &known.frontier(), &known, MAX_EXPLORE_DEPTH);
+            let exit = DijkstraMap::find_lowest_exit(&dm, start, &known)?;
+            if dm.map[exit] < dm.map[start] { Some(map.index_to_point2d(exit)) } else { None }
+        }
+    }
+}
+
+/// Takes one step towards `destination`, or exploring if it's None. Returns false if there is nowhere to go.
+pub fn travel_step(ecs: &mut World, destination: Option<Point>) -> bool {
+    let Some(step) = next_step(ecs, destination) else { return false; };
+    let player_pos = *ecs.fetch::<Point>();
+    try_move_player(step.x - player_pos.x, step.y - player_pos.y, ecs);
+    true
+}
+
+pub fn start_travel(ecs: &mut World, destination: Point) -> RunState {
+    let in_bounds = {
+        let map = ecs.fetch::<Map>();
+        destination.x >= 0 && destination.x < map.width && destination.y >= 0 && destination.y < map.height
+    };
+    if !in_bounds || destination == *ecs.fetch::<Point>() { return RunState::AwaitingInput; }
+
+    let refusal = if hostile_in_view(ecs) {
+        "You can't travel with enemies nearby."
+    } else if next_step(ecs, Some(destination)).is_none() {
+        "You don't know a way there."
+    } else {
+        return RunState::Travelling { destination };
+    };
+    ecs.write_resource::<GameLog>().add(LogBuilder::system().text(refusal));
+    RunState::AwaitingInput
+}
+
+pub fn start_exploring(ecs: &mut World) -> RunState {
+    let refusal = if hostile_in_view(ecs) {
+        "You can't explore with enemies nearby."
+    } else if next_step(ecs, None).is_none() {
+        "There is nowhere left to explore."
+    } else {
+        return RunState::Exploring;
+    };
+    ecs.write_resource::<GameLog>().add(LogBuilder::system().text(refusal));
+    RunState::AwaitingInput
+}
@@ -0,0 +1,88 @@
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use super::prelude::*;
+
+pub struct TriggerSystem {}
+
+/// Somewhere open to land, anywhere on the level.
+fn teleport_destination(rng: &mut RandomNumberGenerator, map: &Map) -> Option<Point> {
+    for _ in 0..100 {
+        let point = Point::new(rng.range(1, map.width - 1), rng.range(1, map.height - 1));
+        let idx = map.point2d_to_index(point);
+        if map.tiles[idx] == TileType::Floor && !map.blocked[idx] && !map.trapped[idx] {
+            return Some(point);
+        }
+    }
+    None
+}
+
+impl<'a> System<'a> for TriggerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( ReadExpect<'a, Map>,
+                        ReadExpect<'a, Entity>,
+                        WriteExpect<'a, Point>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, ParticleBuilder>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        Entities<'a>,
+                        WriteStorage<'a, EntityMoved>,
+                        WriteStorage<'a, Position>,
+                        WriteStorage<'a, Viewshed>,
+                        ReadStorage<'a, EntryTrigger>,
+                        WriteStorage<'a, Hidden>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, InflictsDamage>,
+                        ReadStorage<'a, TeleportsVictim>,
+                        ReadStorage<'a, RaisesAlarm>,
+                        ReadStorage<'a, CausesConfusion>,
+                        WriteStorage<'a, Confusion>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, MakesNoise> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, player_entity, mut player_pos, mut log, mut particles, mut rng, entities, mut moved, mut positions, mut viewsheds, triggers,
+            mut hidden, names, damages, teleports, alarms, confusers, mut confusion, mut inflict_damage, mut noises) = data;
+
+        let movers: Vec<(Entity, Point)> = (&entities, &moved, &positions).join()
+            .map(|(entity, _moved, pos)| (entity, Point::new(pos.x, pos.y)))
+            .collect();
+
+        for (victim, point) in movers {
+            let idx = map.point2d_to_index(point);
+            for trap in map.tile_content[idx].iter().filter(|trap| **trap != victim && triggers.get(**trap).is_some()) {
+                hidden.remove(*trap);
+                if map.visible_tiles[idx] {
+                    let victim_name = names.get(victim).map_or("Something", |name| name.name.as_str());
+                    let trap_name = names.get(*trap).map_or("a trap", |name| name.name.as_str());
+                    log.add(LogBuilder::combat().actor(victim_name, victim == *player_entity).text("triggers the").colour(MAGENTA, trap_name).text("!"));
+                }
+
+                if let Some(spikes) = damages.get(*trap) {
+                    SufferDamage::new_damage(&mut inflict_damage, victim, spikes.damage);
+                    particles.request(point.x, point.y, RGB::named(ORANGE), RGB::named(BLACK), to_cp437('‼'), 200.0);
+                }
+                if let Some(alarm) = alarms.get(*trap) {
+                    MakesNoise::new_noise(&mut noises, *trap, alarm.volume);
+                    particles.request(point.x, point.y, RGB::named(YELLOW), RGB::named(BLACK), to_cp437('!'), 300.0);
+                }
+                if let Some(gas) = confusers.get(*trap) {
+                    confusion.insert(victim, Confusion { turns: gas.turns }).expect("Unable to insert confusion");
+                    particles.request(point.x, point.y, RGB::named(CYAN), RGB::named(BLACK), to_cp437('?'), 300.0);
+                }
+                if teleports.get(*trap).is_some() {
+                    if let (Some(destination), Some(pos)) = (teleport_destination(&mut rng, &map), positions.get_mut(victim)) {
+                        (pos.x, pos.y) = (destination.x, destination.y);
+                        if let Some(viewshed) = viewsheds.get_mut(victim) {
+                            viewshed.dirty = true;
+                        }
+                        if victim == *player_entity {
+                            *player_pos = destination;
+                        }
+                    }
+                }
+            }
+        }
+
+        moved.clear();
+    }
+}
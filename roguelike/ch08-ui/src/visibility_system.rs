@@ -1,31 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
 use bracket_lib::prelude::*;
 use specs::prelude::*;
-use super::prelude::{Viewshed, Position, Map, Player};
+use super::prelude::{Viewshed, Position, Map, Player, Hidden, Name, GameLog, LogBuilder, FovAlgorithm, field_of_view_with};
+
+/// Whenever the player's view updates, they have a one in this many chance of noticing each hidden thing in it.
+const SPOT_CHANCE: i32 = 24;
+/// Forget everything once the cache gets this big, rather than let it grow without bound.
+const MAX_CACHED_VIEWS: usize = 4096;
+
+/// Fields of view by origin and range. Only valid for one revision of the map and one algorithm,
+/// so it empties itself when either changes.
+#[derive(Default)]
+pub struct FovCache {
+    revision: u64,
+    algorithm: FovAlgorithm,
+    views: HashMap<(Point, i32), Vec<Point>>,
+}
+
+impl FovCache {
+    pub fn new() -> Self {
+        FovCache::default()
+    }
+
+    fn sync(&mut self, map: &Map, algorithm: FovAlgorithm) {
+        if self.revision != map.revision || self.algorithm != algorithm || self.views.len() > MAX_CACHED_VIEWS {
+            self.revision = map.revision;
+            self.algorithm = algorithm;
+            self.views.clear();
+        }
+    }
+
+    fn get(&self, origin: Point, range: i32) -> Option<&Vec<Point>> {
+        self.views.get(&(origin, range))
+    }
+
+    fn insert(&mut self, origin: Point, range: i32, view: Vec<Point>) {
+        self.views.insert((origin, range), view);
+    }
+
+    /// The tiles visible from `origin`, computing and remembering them if this is the first time asked.
+    pub fn view(&mut self, origin: Point, range: i32, map: &Map, algorithm: FovAlgorithm) -> &Vec<Point> {
+        self.sync(map, algorithm);
+        self.views.entry((origin, range)).or_insert_with(|| field_of_view_with(algorithm, origin, range, map))
+    }
+}
 
 pub struct VisibilitySystem;
 
 impl<'a> System<'a> for VisibilitySystem {
-    type SystemData = (WriteExpect<'a, Map>, Entities<'a>, WriteStorage<'a, Viewshed>, WriteStorage<'a, Position>, ReadStorage<'a, Player>);
+    #[allow(clippy::type_complexity)]
+    type SystemData = (WriteExpect<'a, Map>, ReadExpect<'a, FovAlgorithm>, WriteExpect<'a, FovCache>, WriteStorage<'a, Viewshed>, WriteStorage<'a, Position>, ReadStorage<'a, Player>,
+                       WriteExpect<'a, RandomNumberGenerator>, WriteStorage<'a, Hidden>, ReadStorage<'a, Name>, WriteExpect<'a, GameLog>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player) = data;
-
-        for (ent, viewshed, pos) in (&entities, &mut viewshed, &pos).join() {
-            if viewshed.dirty {
-                viewshed.dirty = false;
-                viewshed.visible_tiles.clear();
-                viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
-                viewshed.visible_tiles.retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height);
-
-                if player.get(ent).is_some() {
-                    for t in map.visible_tiles.iter_mut() { *t = false };
-                    for vis in viewshed.visible_tiles.iter() {
-                        let idx = map.point2d_to_index(*vis);
-                        map.revealed_tiles[idx] = true;
-                        map.visible_tiles[idx] = true;
+        let (mut map, algorithm, mut cache, mut viewshed, pos, player, mut rng, mut hidden, names, mut log) = data;
+
+        cache.sync(&map, *algorithm);
+        let algorithm = *algorithm;
+        let player_moved = (&viewshed, &player).join().any(|(viewshed, _)| viewshed.dirty);
+
+        // Dirty viewsheds are recomputed in parallel; anything the cache didn't have is added afterwards
+        let computed: Mutex<Vec<(Point, i32, Vec<Point>)>> = Mutex::new(Vec::new());
+        {
+            let map = &*map;
+            let cache = &*cache;
+            (&mut viewshed, &pos).par_join()
+                .filter(|(viewshed, _)| viewshed.dirty)
+                .for_each(|(viewshed, pos)| {
+                    let origin = Point::new(pos.x, pos.y);
+                    let view = match cache.get(origin, viewshed.range) {
+                        Some(view) => view.clone(),
+                        None => {
+                            let view = field_of_view_with(algorithm, origin, viewshed.range, map);
+                            computed.lock().expect("FOV cache poisoned").push((origin, viewshed.range, view.clone()));
+                            view
+                        }
+                    };
+                    viewshed.set_visible(view, map);
+                });
+        }
+        for (origin, range, view) in computed.into_inner().expect("FOV cache poisoned") {
+            cache.insert(origin, range, view);
+        }
+
+        if !player_moved { return; }
+        for (viewshed, _player) in (&viewshed, &player).join() {
+            for t in map.visible_tiles.iter_mut() { *t = false };
+            for vis in viewshed.visible_tiles.iter() {
+                let idx = map.point2d_to_index(*vis);
+                map.revealed_tiles[idx] = true;
+                map.visible_tiles[idx] = true;
+
+                // Passive perception
+                for e in map.tile_content[idx].iter() {
+                    if hidden.get(*e).is_some() && rng.roll_dice(1, SPOT_CHANCE) == 1 {
+                        if let Some(name) = names.get(*e) {
+                            log.add(LogBuilder::system().text("You spot a").colour(MAGENTA, &name.name).text("."));
+                        }
+                        hidden.remove(*e);
                     }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}